@@ -2,9 +2,9 @@ use serde::Deserialize;
 use std::collections::BTreeMap;
 
 use super::catalog_type;
+use super::common_type;
 use crate::common;
 use crate::coreui;
-use super::common_type;
 
 #[derive(Debug, Deserialize)]
 pub struct AssetIcon {
@@ -36,12 +36,13 @@ impl AssetIcon {
             },
             csibitmaplist: coreui::csi::BitmapList {
                 tlv_length: 0,
-                unknown: 1,
+                bitmap_count: 1,
                 zero: 0,
                 rendition_length: 0,
             },
             tlv_data: common::RawData(vec![]),
             rendition_data: None,
+            endian: binrw::Endian::Little,
         }
     }
 }
@@ -2,8 +2,8 @@ use std::collections::BTreeMap;
 use std::path::Path;
 
 use super::coreui;
-use anyhow::Result;
 use anyhow::Context;
+use anyhow::Result;
 use serde_json;
 use std::fs;
 
@@ -17,7 +17,10 @@ static COREUI_VERSION: u32 = 802;
 pub fn compile(document: &str, output_path: &str) -> Result<()> {
     let catalog_path = Path::new(document).join("Contents.json");
     let catalog_str = fs::read(catalog_path)?;
+    // only read to validate the document unless tracing
+    #[cfg_attr(not(feature = "debug-trace"), allow(unused_variables))]
     let catalog: catalog_type::Catalog = serde_json::from_slice(&catalog_str)?;
+    #[cfg(feature = "debug-trace")]
     dbg!(&catalog);
 
     let mut image_set_paths = vec![];
@@ -45,8 +48,10 @@ pub fn compile(document: &str, output_path: &str) -> Result<()> {
 
     for app_icon_set_path in app_icon_set_paths {
         let app_icon_set_path = app_icon_set_path.join("Contents.json");
-        let app_icon_set_str= fs::read(app_icon_set_path)?;
+        let app_icon_set_str = fs::read(app_icon_set_path)?;
+        #[cfg_attr(not(feature = "debug-trace"), allow(unused_variables))]
         let app_icon_image: app_icon_type::AssetIcon = serde_json::from_slice(&app_icon_set_str)?;
+        #[cfg(feature = "debug-trace")]
         dbg!(&app_icon_image);
     }
 
@@ -75,10 +80,12 @@ pub fn compile(document: &str, output_path: &str) -> Result<()> {
         extended_metadata,
         renditionkeyfmt,
         rendition_sha_digests: BTreeMap::new(),
+        rendition_blocks: BTreeMap::new(),
         imagedb,
         facetkeysdb: Vec::new(),
         bitmapkeydb: None,
         appearancedb: None,
+        name_index: coreui::NameIndex::default(),
     };
     let theme_store = coreui::StructuredThemeStore { store };
     let car = coreui::CarUtilAssetStorage { theme_store };
@@ -1,12 +1,16 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Write;
 
 use crate::common;
 use crate::coregraphics;
 use crate::coreui;
+use crate::error::CarError;
 use hex::ToHex;
 use num_traits::FromPrimitive;
 use serde::Serialize;
+use serde_json::json;
 
 // version of the assetutil tool, this is hardcoded to match current version
 pub static VERSION: f64 = 804.3;
@@ -18,6 +22,8 @@ pub struct AssetUtilHeader {
     pub appearances: Option<HashMap<String, u32>>,
     #[serde(rename(serialize = "AssetStorageVersion"))]
     pub asset_storage_version: String,
+    #[serde(rename(serialize = "AssociatedChecksum"))]
+    pub associated_checksum: u32,
     #[serde(rename(serialize = "Authoring Tool"))]
     pub authoring_tool: String,
     #[serde(rename(serialize = "CoreUIVersion"))]
@@ -41,6 +47,16 @@ pub struct AssetUtilHeader {
     pub thinning_parameters: String,
     #[serde(rename(serialize = "Timestamp"))]
     pub timestamp: u32,
+    #[serde(rename(serialize = "UUID"))]
+    pub uuid: String,
+}
+
+// assetutil omits the components of malformed colors instead of an empty array
+fn is_none_or_empty(components: &Option<Vec<f64>>) -> bool {
+    match components {
+        Some(components) => components.is_empty(),
+        None => true,
+    }
 }
 
 pub trait ToAssetUtilHeader {
@@ -52,6 +68,7 @@ impl ToAssetUtilHeader for coreui::CarUtilAssetStorage {
         AssetUtilHeader {
             appearances: self.theme_store.store.appearences(),
             asset_storage_version: self.theme_store.store.version_string(),
+            associated_checksum: self.associated_checksum(),
             authoring_tool: self.theme_store.store.authoring_tool(),
             core_ui_version: self.theme_store.store.header.core_ui_version,
             dump_tool_version: VERSION,
@@ -63,23 +80,175 @@ impl ToAssetUtilHeader for coreui::CarUtilAssetStorage {
             storage_version: self.theme_store.store.header.storage_version,
             timestamp: self.theme_store.store.header.storage_timestamp,
             thinning_parameters: self.theme_store.store.thinning_arguments(),
+            uuid: self.theme_store.store.uuid_string(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+pub trait ToAssetUtilJson {
+    fn write_assetutil_json<W: Write>(&self, writer: W) -> Result<(), CarError>;
+    fn to_assetutil_json(&self) -> serde_json::Value;
+    fn write_ndjson<W: Write>(&self, writer: &mut W) -> Result<(), CarError>;
+}
+
+impl ToAssetUtilJson for coreui::CarUtilAssetStorage {
+    // the same document as to_assetutil_json(), one object per rendition is
+    // written at a time instead of building the whole serde_json::Value first
+    fn write_assetutil_json<W: Write>(&self, mut writer: W) -> Result<(), CarError> {
+        writeln!(writer, "[")?;
+        serde_json::to_writer_pretty(&mut writer, &self.asset_util_header())?;
+        for entry in sorted_entries(&self.theme_store.store) {
+            writeln!(writer, ",")?;
+            serde_json::to_writer_pretty(&mut writer, &entry)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "]")?;
+        Ok(())
+    }
+
+    // the header followed by the entries sorted like assetutil does, so the
+    // output can be diffed
+    fn to_assetutil_json(&self) -> serde_json::Value {
+        let mut result = vec![json!(self.asset_util_header())];
+        result.extend(
+            sorted_entries(&self.theme_store.store)
+                .into_iter()
+                .map(|entry| json!(entry)),
+        );
+        serde_json::Value::Array(result)
+    }
+
+    // the header then one entry per line, in the order of the catalog, for
+    // tools like jq -c
+    fn write_ndjson<W: Write>(&self, writer: &mut W) -> Result<(), CarError> {
+        serde_json::to_writer(&mut *writer, &self.asset_util_header())?;
+        writeln!(writer)?;
+        for entry in AssetUtilEntry::iter_from_asset_storage(&self.theme_store.store) {
+            serde_json::to_writer(&mut *writer, &entry)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn sorted_entries(asset_storage: &coreui::CommonAssetStorage) -> Vec<AssetUtilEntry> {
+    let mut entries = AssetUtilEntry::entries_from_asset_storage(asset_storage);
+    entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    entries
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlistFormat {
+    Xml,
+    Binary,
+}
+
+pub trait ToAssetUtilPlist {
+    fn to_plist<W: Write>(&self, writer: &mut W, format: PlistFormat) -> Result<(), CarError>;
+}
+
+// the array of to_assetutil_json() as a plist, like assetutil can write
+impl ToAssetUtilPlist for coreui::CarUtilAssetStorage {
+    fn to_plist<W: Write>(&self, writer: &mut W, format: PlistFormat) -> Result<(), CarError> {
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Item {
+            Header(AssetUtilHeader),
+            Entry(Box<AssetUtilEntry>),
+        }
+
+        let mut items = vec![Item::Header(self.asset_util_header())];
+        items.extend(
+            sorted_entries(&self.theme_store.store)
+                .into_iter()
+                .map(|entry| Item::Entry(Box::new(entry))),
+        );
+        match format {
+            PlistFormat::Xml => plist::to_writer_xml(writer, &items)?,
+            PlistFormat::Binary => plist::to_writer_binary(writer, &items)?,
+        }
+        Ok(())
+    }
+}
+
+pub trait ToAssetUtilCsv {
+    fn write_csv<W: Write>(&self, writer: W) -> Result<(), CarError>;
+}
+
+static CSV_COLUMNS: [&str; 9] = [
+    "Name",
+    "AssetType",
+    "Idiom",
+    "Scale",
+    "PixelWidth",
+    "PixelHeight",
+    "Compression",
+    "SizeOnDisk",
+    "SHA1Digest",
+];
+
+// one row per rendition, missing fields are left blank
+impl ToAssetUtilCsv for coreui::CommonAssetStorage {
+    fn write_csv<W: Write>(&self, mut writer: W) -> Result<(), CarError> {
+        writeln!(writer, "{}", CSV_COLUMNS.join(","))?;
+        for entry in AssetUtilEntry::entries_from_asset_storage(self) {
+            let row = [
+                csv_field(&entry.name)?,
+                csv_field(&entry.asset_type)?,
+                csv_field(&entry.idiom)?,
+                csv_field(&entry.scale)?,
+                csv_field(&entry.pixel_width)?,
+                csv_field(&entry.pixel_height)?,
+                csv_field(&entry.compression)?,
+                csv_field(&entry.size_on_disk)?,
+                csv_field(&entry.sha1_digest)?,
+            ];
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// formats the value like in the JSON output, quoting it when needed
+fn csv_field<T: Serialize>(value: &T) -> Result<String, CarError> {
+    let field = match serde_json::to_value(value)? {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(string) => string,
+        value => value.to_string(),
+    };
+    if field.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+        Ok(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Ok(field)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct AssetUtilEntry {
+    #[serde(rename(serialize = "AlphaCropped"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_cropped: Option<bool>,
+    #[serde(rename(serialize = "AlphaCroppedFrame"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_cropped_frame: Option<coregraphics::Rect>,
     #[serde(rename(serialize = "Appearance"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub appearance: Option<String>,
     #[serde(rename(serialize = "AssetType"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub asset_type: Option<String>,
+    #[serde(rename(serialize = "BitmapEncoding"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitmap_encoding: Option<String>,
     #[serde(rename(serialize = "BitsPerComponent"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bits_per_component: Option<u32>,
-    #[serde(rename(serialize = "Color components"))]
+    #[serde(rename(serialize = "BlurRadius"))]
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub blur_radius: Option<f32>,
+    #[serde(rename(serialize = "Color components"))]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub color_components: Option<Vec<f64>>,
     #[serde(rename(serialize = "ColorModel"))]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -93,12 +262,33 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "Data Length"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data_length: Option<u32>,
+    #[serde(rename(serialize = "Direction"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<AttributeValue<coreui::rendition::Direction>>,
+    #[serde(rename(serialize = "DisplayGamut"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_gamut: Option<AttributeValue<coreui::rendition::DisplayGamut>>,
     #[serde(rename(serialize = "Encoding"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub encoding: Option<coreui::csi::PixelFormat>,
+    #[serde(rename(serialize = "EXIFOrientation"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exif_orientation: Option<coreui::tlv::EXIFOrientationValue>,
+    #[serde(rename(serialize = "Flippable"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flippable: Option<bool>,
+    #[serde(rename(serialize = "Hotspot"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotspot: Option<Hotspot>,
     #[serde(rename(serialize = "Idiom"))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub idiom: Option<coreui::rendition::Idiom>,
+    pub idiom: Option<AttributeValue<coreui::rendition::Idiom>>,
+    #[serde(rename(serialize = "Layers"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Vec<LayerReference>>,
+    #[serde(rename(serialize = "LayoutType"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout_type: Option<u32>,
     #[serde(rename(serialize = "Name"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -108,6 +298,9 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "Opaque"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub opaque: Option<bool>,
+    #[serde(rename(serialize = "Opacity"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
     #[serde(rename(serialize = "PixelHeight"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pixel_height: Option<u32>,
@@ -117,12 +310,21 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "RenditionName"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rendition_name: Option<String>,
+    #[serde(rename(serialize = "ResizableImage"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resizable_image: Option<ResizableImage>,
     #[serde(rename(serialize = "Scale"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scale: Option<u32>,
     #[serde(rename(serialize = "SHA1Digest"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sha1_digest: Option<String>, // Actually SHA256
+    #[serde(rename(serialize = "SizeClassHorizontal"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_class_horizontal: Option<AttributeValue<coreui::rendition::SizeClass>>,
+    #[serde(rename(serialize = "SizeClassVertical"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_class_vertical: Option<AttributeValue<coreui::rendition::SizeClass>>,
     #[serde(rename(serialize = "SizeOnDisk"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub size_on_disk: Option<u32>,
@@ -135,20 +337,122 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "Template Mode"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub template_mode: Option<coreui::rendition::TemplateMode>,
+    #[serde(rename(serialize = "Tintable"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tintable: Option<bool>,
     #[serde(rename(serialize = "UTI"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uti: Option<String>,
     #[serde(rename(serialize = "Value"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<coreui::rendition::Value>,
+    #[serde(rename(serialize = "Vector Based"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_based: Option<bool>,
+}
+
+// attribute values unknown to this version are reported by their raw value
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AttributeValue<T> {
+    Known(T),
+    Unknown(u16),
+}
+
+impl<T: FromPrimitive> AttributeValue<T> {
+    fn from_u16(value: u16) -> Self {
+        match T::from_u16(value) {
+            Some(known) => AttributeValue::Known(known),
+            None => AttributeValue::Unknown(value),
+        }
+    }
+}
+
+fn find_attribute(
+    rendition_key_values: &[(coreui::rendition::AttributeType, u16)],
+    attribute: coreui::rendition::AttributeType,
+) -> Option<u16> {
+    rendition_key_values
+        .iter()
+        .find(|(attribute_type, _)| *attribute_type == attribute)
+        .map(|(_, value)| *value)
+}
+
+// cursor hotspot from the facet key, omitted when (0, 0)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Hotspot {
+    pub x: u16,
+    pub y: u16,
+}
+
+// link to a layer of a layer stack, the layer is named by its key
+#[derive(Debug, Serialize)]
+pub struct LayerReference {
+    #[serde(rename(serialize = "Frame"))]
+    pub frame: coregraphics::Rect,
+    #[serde(rename(serialize = "NameIdentifier"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_identifier: Option<u16>,
+}
+
+impl LayerReference {
+    pub fn from_rendition_link(rendition_link: &coreui::rendition::RenditionLink) -> Self {
+        let frame = rendition_link.frame();
+        LayerReference {
+            frame: coregraphics::Rect {
+                origin: coregraphics::Point {
+                    x: frame.x as f64,
+                    y: frame.y as f64,
+                },
+                size: coregraphics::Size {
+                    width: frame.width as f64,
+                    height: frame.height as f64,
+                },
+            },
+            name_identifier: rendition_link.identifier(),
+        }
+    }
+}
+
+// slicing of a 3 or 9-part image, not reported by assetutil
+#[derive(Debug, Serialize)]
+pub struct ResizableImage {
+    #[serde(rename(serialize = "capInsets"))]
+    pub cap_insets: coregraphics::EdgeInsets,
+    #[serde(rename(serialize = "resizingMode"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resizing_mode: Option<coreui::rendition::ResizingMode>,
 }
 
 impl AssetUtilEntry {
+    pub fn builder() -> AssetUtilEntryBuilder {
+        AssetUtilEntryBuilder::default()
+    }
+
+    // (Name, Idiom, Scale, Appearance), idioms are ordered by their raw value
+    fn sort_key(&self) -> (Option<&str>, Option<u16>, Option<u32>, Option<&str>) {
+        let idiom = self.idiom.as_ref().map(|idiom| match idiom {
+            AttributeValue::Known(idiom) => idiom.clone() as u16,
+            AttributeValue::Unknown(value) => *value,
+        });
+        (
+            self.name.as_deref(),
+            idiom,
+            self.scale,
+            self.appearance.as_deref(),
+        )
+    }
+
     pub fn entries_from_asset_storage(
         asset_storage: &coreui::CommonAssetStorage,
     ) -> Vec<AssetUtilEntry> {
-        let mut result = vec![];
+        Self::iter_from_asset_storage(asset_storage).collect()
+    }
 
+    // entries are built one rendition at a time, in the order of the catalog
+    pub fn iter_from_asset_storage(
+        asset_storage: &coreui::CommonAssetStorage,
+    ) -> impl Iterator<Item = AssetUtilEntry> + '_ {
         let name_identifer_to_facet_key = asset_storage
             .facetkeysdb
             .iter()
@@ -159,42 +463,95 @@ impl AssetUtilEntry {
                     .find(|attribute| {
                         attribute.name == coreui::rendition::AttributeType16::Identifier
                     })
-                    .and_then(|attribute| Some((attribute.value, name.to_string())))
+                    .and_then(|attribute| {
+                        Some((
+                            attribute.value,
+                            (name.to_string(), key_token.cursor_hotspot()),
+                        ))
+                    })
             })
             .flatten()
-            .collect::<HashMap<u16, String>>();
-
-        for (rendition_key, csi_header) in &asset_storage.imagedb {
-            let rendition_key_values: Vec<(coreui::rendition::AttributeType, u16)> =
-                asset_storage.renditionkeyfmt.map(rendition_key);
-            let name_identifier = rendition_key_values
-                .iter()
-                .find(|(attribute, _)| *attribute == coreui::rendition::AttributeType::Identifier)
-                .and_then(|(_, value)| Some(value));
-            let facet_key = if let Some(name_identifier) = name_identifier {
-                name_identifer_to_facet_key.get(&name_identifier).cloned()
-            } else {
-                None
-            };
-            let sha_digest = asset_storage
-                .rendition_sha_digests
-                .get(rendition_key)
-                .cloned()
-                .unwrap_or_default();
-            let entry = AssetUtilEntry::from_csi_header(
-                &csi_header,
-                facet_key,
-                rendition_key_values,
-                sha_digest,
-                asset_storage
-                    .appearancedb
-                    .as_ref()
-                    .unwrap_or(&BTreeMap::new()),
-            );
-            result.push(entry);
-        }
+            .collect::<HashMap<u16, (String, (u16, u16))>>();
+        let appearancedb = asset_storage.appearancedb.clone().unwrap_or_default();
+        // facets with a rendition for a wide gamut, assetutil reports sRGB for
+        // their other images
+        let wide_gamut_identifiers = asset_storage
+            .imagedb
+            .keys()
+            .filter_map(|rendition_key| {
+                let rendition_key_values = asset_storage.renditionkeyfmt.map(rendition_key);
+                let display_gamut = find_attribute(
+                    &rendition_key_values,
+                    coreui::rendition::AttributeType::DisplayGamut,
+                );
+                match display_gamut {
+                    Some(display_gamut) if display_gamut > 0 => find_attribute(
+                        &rendition_key_values,
+                        coreui::rendition::AttributeType::Identifier,
+                    ),
+                    _ => None,
+                }
+            })
+            .collect::<HashSet<u16>>();
 
-        result
+        asset_storage
+            .imagedb
+            .iter()
+            .map(move |(rendition_key, csi_header)| {
+                let rendition_key_values: Vec<(coreui::rendition::AttributeType, u16)> =
+                    asset_storage.renditionkeyfmt.map(rendition_key);
+                let is_srgb_variant = matches!(
+                    csi_header.csimetadata.layout,
+                    coreui::rendition::LayoutType32::Image
+                        | coreui::rendition::LayoutType32::PackedImage
+                ) && find_attribute(
+                    &rendition_key_values,
+                    coreui::rendition::AttributeType::DisplayGamut,
+                ) == Some(coreui::rendition::DisplayGamut::SRGB as u16)
+                    && find_attribute(
+                        &rendition_key_values,
+                        coreui::rendition::AttributeType::Identifier,
+                    )
+                    .map_or(false, |identifier| {
+                        wide_gamut_identifiers.contains(&identifier)
+                    });
+                let name_identifier = rendition_key_values
+                    .iter()
+                    .find(|(attribute, _)| {
+                        *attribute == coreui::rendition::AttributeType::Identifier
+                    })
+                    .and_then(|(_, value)| Some(value));
+                let (facet_key, hotspot) = match name_identifier
+                    .and_then(|name_identifier| name_identifer_to_facet_key.get(name_identifier))
+                {
+                    Some((facet_key, hotspot)) => (Some(facet_key.clone()), *hotspot),
+                    None => (None, (0, 0)),
+                };
+                let sha_digest = asset_storage
+                    .rendition_sha_digests
+                    .get(rendition_key)
+                    .cloned()
+                    .unwrap_or_default();
+                let mut entry = AssetUtilEntry::from_csi_header(
+                    &csi_header,
+                    facet_key,
+                    rendition_key_values,
+                    sha_digest,
+                    &appearancedb,
+                );
+                if hotspot != (0, 0) {
+                    entry.hotspot = Some(Hotspot {
+                        x: hotspot.0,
+                        y: hotspot.1,
+                    });
+                }
+                if is_srgb_variant {
+                    entry.display_gamut = Some(AttributeValue::from_u16(
+                        coreui::rendition::DisplayGamut::SRGB as u16,
+                    ));
+                }
+                entry
+            })
     }
 
     pub fn from_csi_header(
@@ -206,6 +563,18 @@ impl AssetUtilEntry {
     ) -> AssetUtilEntry {
         let layout = csi_header.csimetadata.layout;
 
+        let is_image = matches!(
+            layout,
+            coreui::rendition::LayoutType32::Image | coreui::rendition::LayoutType32::PackedImage
+        );
+
+        // the flags below are only reported when set
+        let alpha_cropped = (is_image && csi_header.is_alpha_cropped()).then_some(true);
+        let alpha_cropped_frame = match alpha_cropped {
+            Some(true) => csi_header.framing_rect(),
+            _ => None,
+        };
+
         let appearance: Option<String> =
             rendition_key_values
                 .iter()
@@ -230,16 +599,32 @@ impl AssetUtilEntry {
         let asset_type = match layout {
             coreui::rendition::LayoutType32::Color => Some("Color".to_string()),
             coreui::rendition::LayoutType32::Data => Some("Data".to_string()),
-            coreui::rendition::LayoutType32::Image => Some("Image".to_string()),
+            coreui::rendition::LayoutType32::Image
+            | coreui::rendition::LayoutType32::LayerStack => Some("Image".to_string()),
             coreui::rendition::LayoutType32::MultisizeImage => Some("MultiSized Image".to_string()),
             coreui::rendition::LayoutType32::PackedImage => Some("PackedImage".to_string()),
+            coreui::rendition::LayoutType32::Vector => Some("Vector".to_string()),
+            coreui::rendition::LayoutType32::Unknown(_) => Some("Unknown".to_string()),
+            _ => None,
+        };
+
+        // raw value of layouts added by newer versions of CoreUI
+        let layout_type = match layout {
+            coreui::rendition::LayoutType32::Unknown(layout_type) => Some(layout_type),
+            _ => None,
+        };
+
+        // not reported by assetutil, only emitted when it doesn't match the pixel format
+        let bitmap_encoding = match csi_header.bitmap_encoding() {
+            bitmap_encoding if is_image && !bitmap_encoding.matches(csi_header.pixel_format) => {
+                Some(bitmap_encoding.to_string())
+            }
             _ => None,
         };
 
-        // TODO: fix
         let bits_per_component = match layout {
             coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(8),
+            | coreui::rendition::LayoutType32::Image => Some(csi_header.bits_per_component()),
             _ => None,
         };
 
@@ -252,38 +637,26 @@ impl AssetUtilEntry {
 
         let color_model = match layout {
             coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => csi_header.color_space.color_model(),
+            | coreui::rendition::LayoutType32::Image => {
+                Some(csi_header.color_space.color_space().color_model())
+            }
             _ => None,
         };
 
-        // TODO: fix
         let colorspace = match &csi_header.rendition_data {
             Some(coreui::rendition::Rendition::Theme { .. })
-            | Some(coreui::rendition::Rendition::ThemeCBCK { .. })
-            | Some(coreui::rendition::Rendition::Color { .. }) => match color_model {
-                Some(coregraphics::ColorModel::Monochrome) => {
-                    Some(coregraphics::ColorSpace::GrayGamma2_2)
-                }
-                _ => Some(coregraphics::ColorSpace::SRGB),
-            },
+            | Some(coreui::rendition::Rendition::ThemeCBCK { .. }) => {
+                Some(csi_header.color_space.color_space())
+            }
+            Some(coreui::rendition::Rendition::Color { flags, .. }) => Some(flags.color_space()),
             _ => None,
         };
 
-        let compression = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::Theme {
-                compression_type, ..
-            }) => Some(*compression_type),
-            Some(coreui::rendition::Rendition::ThemeCBCK {
-                compression_type, ..
-            }) => Some(*compression_type),
-            Some(coreui::rendition::Rendition::RawData { .. }) => match layout {
-                coreui::rendition::LayoutType32::Data => {
-                    Some(coreui::rendition::CompressionType::Uncompressed)
-                }
-                _ => None,
-            },
-            _ => None,
-        };
+        let compression = csi_header.compression_type();
+
+        let blur_radius = csi_header
+            .blurred_image()
+            .map(|blurred_image| blurred_image.radius);
 
         let data_length = match &csi_header.rendition_data {
             Some(coreui::rendition::Rendition::RawData {
@@ -295,16 +668,42 @@ impl AssetUtilEntry {
             _ => None,
         };
 
+        // 0 means the rendition is used for both directions
+        let direction = find_attribute(
+            &rendition_key_values,
+            coreui::rendition::AttributeType::Direction,
+        )
+        .filter(|value| *value > 0)
+        .map(AttributeValue::from_u16);
+
+        // sRGB is the default and is left out like assetutil does
+        let display_gamut = find_attribute(
+            &rendition_key_values,
+            coreui::rendition::AttributeType::DisplayGamut,
+        )
+        .filter(|value| *value != coreui::rendition::DisplayGamut::SRGB as u16)
+        .map(AttributeValue::from_u16);
+
         let encoding = match layout {
             coreui::rendition::LayoutType32::Image
             | coreui::rendition::LayoutType32::PackedImage => Some(csi_header.pixel_format),
             _ => None,
         };
 
-        let idiom: Option<coreui::rendition::Idiom> = rendition_key_values
-            .iter()
-            .find(|(attribute, _)| *attribute == coreui::rendition::AttributeType::Idiom)
-            .and_then(|(_, value)| FromPrimitive::from_u16(*value));
+        let exif_orientation = match csi_header.exif_orientation() {
+            Some(coreui::tlv::EXIFOrientationValue::None)
+            | Some(coreui::tlv::EXIFOrientationValue::Normal) => None,
+            exif_orientation if is_image => exif_orientation,
+            _ => None,
+        };
+
+        let flippable = (is_image && csi_header.rendition_flags.is_flippable()).then_some(true);
+
+        let idiom = find_attribute(
+            &rendition_key_values,
+            coreui::rendition::AttributeType::Idiom,
+        )
+        .map(AttributeValue::from_u16);
 
         let name_identifier = rendition_key_values
             .iter()
@@ -319,47 +718,51 @@ impl AssetUtilEntry {
             _ => None,
         };
 
-        let mut pixel_height = match layout {
+        // not reported by assetutil, only emitted when an image isn't fully opaque
+        let opacity = match layout {
+            coreui::rendition::LayoutType32::Image
+            | coreui::rendition::LayoutType32::PackedImage => {
+                csi_header.opacity().filter(|opacity| *opacity != 1.0)
+            }
+            _ => None,
+        };
+
+        let pixel_height = match layout {
             coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(csi_header.height),
+            | coreui::rendition::LayoutType32::Image => csi_header.pixel_height(),
             _ => None,
         };
-        if pixel_height == Some(0) {
-            pixel_height = csi_header
-                .properties()
-                .into_iter()
-                .find_map(|attribute_type| match attribute_type {
-                    coreui::tlv::RenditionType::Slices { height, .. } => Some(height),
-                    _ => None,
-                })
-        }
 
-        let mut pixel_width = match layout {
+        let pixel_width = match layout {
             coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(csi_header.width),
+            | coreui::rendition::LayoutType32::Image => csi_header.pixel_width(),
             _ => None,
         };
-        if pixel_width == Some(0) {
-            pixel_width = csi_header
-                .properties()
-                .into_iter()
-                .find_map(|attribute_type| match attribute_type {
-                    coreui::tlv::RenditionType::Slices { width, .. } => Some(width),
-                    _ => None,
-                })
-        }
 
         let rendition_name = match layout {
             coreui::rendition::LayoutType32::Image => Some(csi_header.csimetadata.name()),
             coreui::rendition::LayoutType32::PackedImage => Some(csi_header.csimetadata.name()),
             _ => None,
         };
+        let resizable_image = match layout {
+            coreui::rendition::LayoutType32::PackedImage
+            | coreui::rendition::LayoutType32::Image => {
+                csi_header.cap_insets().map(|cap_insets| ResizableImage {
+                    cap_insets,
+                    resizing_mode: csi_header.rendition_flags.resizing_mode(),
+                })
+            }
+            _ => None,
+        };
+
         let name = if facet_key.is_some() {
             facet_key
         } else {
             rendition_name.clone()
         };
 
+        // assetutil reports a scale for every asset type, scale-agnostic renditions
+        // (e.g. colors) store 0 and are reported as 1
         let scale = if csi_header.scale_factor == 0 {
             Some(1)
         } else {
@@ -367,25 +770,29 @@ impl AssetUtilEntry {
         };
 
         let sha1_digest = Some(sha_digest.encode_hex_upper());
-        let size_on_disk = Some(
-            // 184 is the size of the csi header struct
-            184 + csi_header.csibitmaplist.tlv_length + csi_header.csibitmaplist.rendition_length,
-        );
+        let size_on_disk = Some(csi_header.size_on_disk());
 
-        let sizes = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::MultisizeImageSet { entries, .. }) => Some(
-                entries
-                    .iter()
-                    .map(|entry| {
-                        format!(
-                            "{}x{} index:{} idiom:{:?}",
-                            entry.width, entry.height, entry.index, entry.idiom
-                        )
-                    })
-                    .collect(),
-            ),
-            _ => None,
+        // 0 means any size class
+        let size_class = |attribute| {
+            find_attribute(&rendition_key_values, attribute)
+                .filter(|value| *value > 0)
+                .map(AttributeValue::from_u16)
         };
+        let size_class_horizontal =
+            size_class(coreui::rendition::AttributeType::SizeClassHorizontal);
+        let size_class_vertical = size_class(coreui::rendition::AttributeType::SizeClassVertical);
+
+        let sizes = csi_header.multisize_image_set_entries().map(|entries| {
+            entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{}x{} index:{} idiom:{:?}",
+                        entry.width, entry.height, entry.index, entry.idiom
+                    )
+                })
+                .collect()
+        });
 
         let state = rendition_key_values.iter().find_map(|(attribute, value)| {
             if *attribute == coreui::rendition::AttributeType::State {
@@ -424,6 +831,8 @@ impl AssetUtilEntry {
             _ => None,
         };
 
+        let tintable = (is_image && csi_header.rendition_flags.is_tintable()).then_some(true);
+
         let value = rendition_key_values.iter().find_map(|(attribute, value)| {
             if *attribute == coreui::rendition::AttributeType::Value {
                 FromPrimitive::from_u16(*value)
@@ -448,31 +857,278 @@ impl AssetUtilEntry {
             _ => None,
         };
 
+        // links to the layers composited by a layer stack
+        let layers = csi_header.layer_stack_layers().map(|layers| {
+            layers
+                .iter()
+                .map(LayerReference::from_rendition_link)
+                .collect()
+        });
+
+        let vector_based = match layout {
+            coreui::rendition::LayoutType32::Vector => Some(true),
+            _ => (is_image && csi_header.rendition_flags.is_vector_based()).then_some(true),
+        };
+
         AssetUtilEntry {
+            alpha_cropped,
+            alpha_cropped_frame,
             appearance,
             asset_type,
+            bitmap_encoding,
             bits_per_component,
+            blur_radius,
             color_components,
             color_model,
             colorspace,
             compression,
             data_length,
+            direction,
+            display_gamut,
             encoding,
+            exif_orientation,
+            flippable,
+            hotspot: None,
             idiom,
+            layers,
+            layout_type,
             name,
             name_identifier,
             opaque,
+            opacity,
             pixel_height,
             pixel_width,
             rendition_name,
+            resizable_image,
             scale,
             sha1_digest,
+            size_class_horizontal,
+            size_class_vertical,
             size_on_disk,
             sizes,
             state,
             template_mode,
+            tintable,
             uti,
             value,
+            vector_based,
         }
     }
 }
+
+// builds entries without parsing a .car file, unset fields are omitted from the output
+#[derive(Debug, Default)]
+pub struct AssetUtilEntryBuilder {
+    entry: AssetUtilEntry,
+}
+
+impl AssetUtilEntryBuilder {
+    pub fn alpha_cropped(mut self, alpha_cropped: bool) -> Self {
+        self.entry.alpha_cropped = Some(alpha_cropped);
+        self
+    }
+
+    pub fn alpha_cropped_frame(mut self, alpha_cropped_frame: coregraphics::Rect) -> Self {
+        self.entry.alpha_cropped_frame = Some(alpha_cropped_frame);
+        self
+    }
+
+    pub fn appearance(mut self, appearance: &str) -> Self {
+        self.entry.appearance = Some(appearance.to_string());
+        self
+    }
+
+    pub fn asset_type(mut self, asset_type: &str) -> Self {
+        self.entry.asset_type = Some(asset_type.to_string());
+        self
+    }
+
+    pub fn bitmap_encoding(mut self, bitmap_encoding: &str) -> Self {
+        self.entry.bitmap_encoding = Some(bitmap_encoding.to_string());
+        self
+    }
+
+    pub fn bits_per_component(mut self, bits_per_component: u32) -> Self {
+        self.entry.bits_per_component = Some(bits_per_component);
+        self
+    }
+
+    pub fn blur_radius(mut self, blur_radius: f32) -> Self {
+        self.entry.blur_radius = Some(blur_radius);
+        self
+    }
+
+    pub fn color_components(mut self, color_components: Vec<f64>) -> Self {
+        self.entry.color_components = Some(color_components);
+        self
+    }
+
+    pub fn color_model(mut self, color_model: coregraphics::ColorModel) -> Self {
+        self.entry.color_model = Some(color_model);
+        self
+    }
+
+    pub fn colorspace(mut self, colorspace: coregraphics::ColorSpace) -> Self {
+        self.entry.colorspace = Some(colorspace);
+        self
+    }
+
+    pub fn compression(mut self, compression: coreui::rendition::CompressionType) -> Self {
+        self.entry.compression = Some(compression);
+        self
+    }
+
+    pub fn data_length(mut self, data_length: u32) -> Self {
+        self.entry.data_length = Some(data_length);
+        self
+    }
+
+    pub fn direction(mut self, direction: coreui::rendition::Direction) -> Self {
+        self.entry.direction = Some(AttributeValue::Known(direction));
+        self
+    }
+
+    pub fn display_gamut(mut self, display_gamut: coreui::rendition::DisplayGamut) -> Self {
+        self.entry.display_gamut = Some(AttributeValue::Known(display_gamut));
+        self
+    }
+
+    pub fn encoding(mut self, encoding: coreui::csi::PixelFormat) -> Self {
+        self.entry.encoding = Some(encoding);
+        self
+    }
+
+    pub fn exif_orientation(mut self, exif_orientation: coreui::tlv::EXIFOrientationValue) -> Self {
+        self.entry.exif_orientation = Some(exif_orientation);
+        self
+    }
+
+    pub fn flippable(mut self, flippable: bool) -> Self {
+        self.entry.flippable = Some(flippable);
+        self
+    }
+
+    pub fn hotspot(mut self, x: u16, y: u16) -> Self {
+        self.entry.hotspot = Some(Hotspot { x, y });
+        self
+    }
+
+    pub fn idiom(mut self, idiom: coreui::rendition::Idiom) -> Self {
+        self.entry.idiom = Some(AttributeValue::Known(idiom));
+        self
+    }
+
+    pub fn layers(mut self, layers: Vec<LayerReference>) -> Self {
+        self.entry.layers = Some(layers);
+        self
+    }
+
+    pub fn layout_type(mut self, layout_type: u32) -> Self {
+        self.entry.layout_type = Some(layout_type);
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.entry.name = Some(name.to_string());
+        self
+    }
+
+    pub fn name_identifier(mut self, name_identifier: u16) -> Self {
+        self.entry.name_identifier = Some(name_identifier);
+        self
+    }
+
+    pub fn opaque(mut self, opaque: bool) -> Self {
+        self.entry.opaque = Some(opaque);
+        self
+    }
+
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.entry.opacity = Some(opacity);
+        self
+    }
+
+    pub fn pixel_height(mut self, pixel_height: u32) -> Self {
+        self.entry.pixel_height = Some(pixel_height);
+        self
+    }
+
+    pub fn pixel_width(mut self, pixel_width: u32) -> Self {
+        self.entry.pixel_width = Some(pixel_width);
+        self
+    }
+
+    pub fn rendition_name(mut self, rendition_name: &str) -> Self {
+        self.entry.rendition_name = Some(rendition_name.to_string());
+        self
+    }
+
+    pub fn resizable_image(mut self, resizable_image: ResizableImage) -> Self {
+        self.entry.resizable_image = Some(resizable_image);
+        self
+    }
+
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.entry.scale = Some(scale);
+        self
+    }
+
+    pub fn sha1_digest(mut self, sha1_digest: &str) -> Self {
+        self.entry.sha1_digest = Some(sha1_digest.to_string());
+        self
+    }
+
+    pub fn size_class_horizontal(mut self, size_class: coreui::rendition::SizeClass) -> Self {
+        self.entry.size_class_horizontal = Some(AttributeValue::Known(size_class));
+        self
+    }
+
+    pub fn size_class_vertical(mut self, size_class: coreui::rendition::SizeClass) -> Self {
+        self.entry.size_class_vertical = Some(AttributeValue::Known(size_class));
+        self
+    }
+
+    pub fn size_on_disk(mut self, size_on_disk: u32) -> Self {
+        self.entry.size_on_disk = Some(size_on_disk);
+        self
+    }
+
+    pub fn sizes(mut self, sizes: Vec<String>) -> Self {
+        self.entry.sizes = Some(sizes);
+        self
+    }
+
+    pub fn state(mut self, state: coreui::rendition::State) -> Self {
+        self.entry.state = Some(state);
+        self
+    }
+
+    pub fn template_mode(mut self, template_mode: coreui::rendition::TemplateMode) -> Self {
+        self.entry.template_mode = Some(template_mode);
+        self
+    }
+
+    pub fn tintable(mut self, tintable: bool) -> Self {
+        self.entry.tintable = Some(tintable);
+        self
+    }
+
+    pub fn uti(mut self, uti: &str) -> Self {
+        self.entry.uti = Some(uti.to_string());
+        self
+    }
+
+    pub fn value(mut self, value: coreui::rendition::Value) -> Self {
+        self.entry.value = Some(value);
+        self
+    }
+
+    pub fn vector_based(mut self, vector_based: bool) -> Self {
+        self.entry.vector_based = Some(vector_based);
+        self
+    }
+
+    pub fn build(self) -> AssetUtilEntry {
+        self.entry
+    }
+}
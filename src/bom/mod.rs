@@ -9,8 +9,8 @@ use binrw::io::TakeSeekExt;
 use binrw::meta::ReadEndian;
 use binrw::BinRead;
 use binrw::BinWrite;
+use binrw::Endian;
 use binrw::FilePtr;
-use memmap::Mmap;
 
 type BlockID = u32;
 
@@ -26,6 +26,19 @@ pub struct Storage {
 }
 
 impl Storage {
+    // offset just past the last byte used by this BOM
+    pub fn extent(&self) -> u64 {
+        let index_end = self.block_storage.ptr as u64 + self.block_storage_length as u64;
+        let vars_end = self.var_storage.ptr as u64 + self._unknown_len as u64;
+        self.block_storage
+            .items
+            .iter()
+            .map(|range| range.address as u64 + range.length as u64)
+            .chain([index_end, vars_end])
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn get_named_block_id(&self, name: &str) -> Result<BlockID> {
         (*self.var_storage)
             .vars
@@ -43,7 +56,7 @@ impl Storage {
     pub fn get_named_typed_block<'a, T>(
         &self,
         name: &str,
-        reader: &mut Cursor<Mmap>,
+        reader: &mut Cursor<&[u8]>,
         args: T::Args<'a>,
     ) -> Result<T>
     where
@@ -102,14 +115,14 @@ pub struct BlockRange {
 }
 
 impl BlockRange {
-    pub fn read(&self, cursor: &mut Cursor<Mmap>) -> binrw::BinResult<Vec<u8>> {
+    pub fn read(&self, cursor: &mut Cursor<&[u8]>) -> binrw::BinResult<Vec<u8>> {
         cursor.set_position(self.address as u64);
         helpers::count(self.length as usize)(cursor, binrw::Endian::Little, ())
     }
 
     pub fn read_type<'a, T>(
         &self,
-        cursor: &mut Cursor<Mmap>,
+        cursor: &mut Cursor<&[u8]>,
         args: T::Args<'a>,
     ) -> binrw::BinResult<T>
     where
@@ -182,7 +195,7 @@ pub struct Tree {
 }
 
 impl Tree {
-    pub fn items(&self, storage: &Storage, reader: &mut Cursor<Mmap>) -> Result<Vec<(u32, u32)>> {
+    pub fn items(&self, storage: &Storage, reader: &mut Cursor<&[u8]>) -> Result<Vec<(u32, u32)>> {
         let path_range = storage.block_storage.items[self.path_block_id as usize];
         reader.set_position(path_range.address as u64);
         let path = Paths::read(reader)?;
@@ -193,14 +206,16 @@ impl Tree {
             .collect())
     }
 
+    // keys and values are read with the endianness of the catalog
     pub fn items_typed<T, U>(
         &self,
         storage: &Storage,
-        reader: &mut Cursor<Mmap>,
+        reader: &mut Cursor<&[u8]>,
+        endian: Endian,
     ) -> Result<Vec<(T, U)>>
     where
-        T: BinRead + ReadEndian,
-        U: BinRead + ReadEndian,
+        T: BinRead,
+        U: BinRead,
         for<'a> <T as BinRead>::Args<'a>: Default,
         for<'a> <U as BinRead>::Args<'a>: Default,
     {
@@ -210,11 +225,11 @@ impl Tree {
             .map(|(key, value)| {
                 let key_range = storage.block_storage.items[key as usize];
                 reader.set_position(key_range.address as u64);
-                let key = T::read(reader)?;
+                let key = T::read_options(reader, endian, Default::default())?;
 
                 let value_range = storage.block_storage.items[value as usize];
                 reader.set_position(value_range.address as u64);
-                let value = U::read(reader)?;
+                let value = U::read_options(reader, endian, Default::default())?;
 
                 Ok((key, value))
             })
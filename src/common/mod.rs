@@ -54,9 +54,18 @@ pub fn parse_padded_string(buffer: &[u8]) -> String {
     String::from_utf8_lossy(&buffer[..string_length]).to_string()
 }
 
+// like parse_padded_string, for buffers padded with spaces instead of nulls
+// as found in catalogs from some non-Apple toolchains
+pub fn parse_space_padded_string(buffer: &[u8]) -> String {
+    parse_padded_string(buffer)
+        .trim_end_matches(' ')
+        .to_string()
+}
+
+// strings longer than the slice are cut off at the boundary
 pub fn str_to_sized_slice128(string: &str) -> [u8; 128] {
     let mut slice: [u8; 128] = [0; 128];
-    for (i, c) in string.as_bytes().into_iter().enumerate() {
+    for (i, c) in string.as_bytes().iter().take(slice.len()).enumerate() {
         slice[i] = *c;
     }
     slice
@@ -64,7 +73,7 @@ pub fn str_to_sized_slice128(string: &str) -> [u8; 128] {
 
 pub fn str_to_sized_slice256(string: &str) -> [u8; 256] {
     let mut slice: [u8; 256] = [0; 256];
-    for (i, c) in string.as_bytes().into_iter().enumerate() {
+    for (i, c) in string.as_bytes().iter().take(slice.len()).enumerate() {
         slice[i] = *c;
     }
     slice
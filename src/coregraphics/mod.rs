@@ -1,25 +1,39 @@
 use binrw::BinRead;
 use num_derive::FromPrimitive;
+#[cfg(feature = "serde")]
 use serde::Serialize;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Size {
     pub width: f64,
     pub height: f64,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Rect {
     pub origin: Point,
     pub size: Size,
 }
 
+// distances from each edge, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct EdgeInsets {
+    pub top: f64,
+    pub left: f64,
+    pub bottom: f64,
+    pub right: f64,
+}
+
 #[derive(Debug)]
 pub struct Color {
     pub alpha: f64,
@@ -30,30 +44,58 @@ pub struct Color {
     // pattern: u32,
 }
 
-#[derive(Debug, FromPrimitive, BinRead, Clone, Serialize)]
+#[derive(Debug, FromPrimitive, BinRead, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[br(repr(u32))]
 pub enum ColorSpace {
-    #[serde(rename = "srgb")]
+    #[cfg_attr(feature = "serde", serde(rename = "srgb"))]
     SRGB = 0,
-    #[serde(rename = "gray gamma 22")]
+    #[cfg_attr(feature = "serde", serde(rename = "gray gamma 22"))]
     GrayGamma2_2,
-    #[serde(rename = "p3")]
+    #[cfg_attr(feature = "serde", serde(rename = "p3"))]
     DisplayP3,
-    #[serde(rename = "extended srgb")]
+    #[cfg_attr(feature = "serde", serde(rename = "extended srgb"))]
     ExtendedRangeSRGB,
-    #[serde(rename = "extended linear srgb")]
+    #[cfg_attr(feature = "serde", serde(rename = "extended linear srgb"))]
     ExtendedLinearSRGB,
-    #[serde(rename = "extended gray")]
+    #[cfg_attr(feature = "serde", serde(rename = "extended gray"))]
     ExtendedGray,
+    #[cfg_attr(feature = "serde", serde(rename = "unknown"))]
+    Unknown = 14,
+}
+
+impl ColorSpace {
+    // ids stored by CoreUI in CSI headers and color renditions, they are one
+    // off from the discriminants: catalogs built for sRGB store 1
+    pub fn from_id(id: u32) -> Option<ColorSpace> {
+        match id {
+            1 => Some(ColorSpace::SRGB),
+            2 => Some(ColorSpace::GrayGamma2_2),
+            3 => Some(ColorSpace::DisplayP3),
+            4 => Some(ColorSpace::ExtendedRangeSRGB),
+            5 => Some(ColorSpace::ExtendedLinearSRGB),
+            6 => Some(ColorSpace::ExtendedGray),
+            14 => Some(ColorSpace::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn color_model(&self) -> ColorModel {
+        match self {
+            ColorSpace::GrayGamma2_2 | ColorSpace::ExtendedGray => ColorModel::Monochrome,
+            _ => ColorModel::RGB,
+        }
+    }
 }
 
-#[derive(Debug, FromPrimitive, BinRead, Clone, Serialize)]
+#[derive(Debug, FromPrimitive, BinRead, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[br(repr(u32))]
 pub enum ColorModel {
     None = 0, // ???
     RGB,
     Monochrome,
-    #[serde(rename = "RGB")]
+    #[cfg_attr(feature = "serde", serde(rename = "RGB"))]
     AlsoRGB = 14, // ???
 }
 
@@ -3,7 +3,6 @@ use binrw::BinWrite;
 use std::fmt::Debug;
 
 #[derive(BinRead, BinWrite)]
-#[brw(little)]
 pub struct Key {
     pub raw: [u16; 11],
 }
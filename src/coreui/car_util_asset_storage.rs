@@ -1,22 +1,40 @@
 use super::bitmap;
 use super::csi;
 use super::rendition;
+use super::Facet;
+use super::Sprite;
+use super::SpriteMap;
+use super::SpriteRect;
+use super::SpriteSheet;
+use super::SpriteSheetOptions;
+use anyhow::Context;
 use anyhow::Result;
 use binrw::BinRead;
+use binrw::BinResult;
 use binrw::BinWrite;
+use binrw::Endian;
 use binrw::NullString;
 use memmap::Mmap;
+use num_traits::FromPrimitive;
 use sha2::Digest;
 use sha2::Sha256;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use crate::bom;
 use crate::common;
+use crate::error::CarError;
 
 pub type NameIdentifier = u32;
 
@@ -25,7 +43,11 @@ pub struct CarUtilAssetStorage {
 }
 
 impl CarUtilAssetStorage {
-    pub fn from(path: &str, _for_writing: bool) -> Result<CarUtilAssetStorage> {
+    pub fn from(path: &str, for_writing: bool) -> Result<CarUtilAssetStorage, CarError> {
+        Self::read(path, for_writing).map_err(CarError::from)
+    }
+
+    fn read(path: &str, _for_writing: bool) -> Result<CarUtilAssetStorage> {
         let file = fs::File::open(path)?;
         let file_timestamp: u32;
         {
@@ -34,38 +56,80 @@ impl CarUtilAssetStorage {
             let duration = modified.duration_since(UNIX_EPOCH)?;
             file_timestamp = duration.as_secs().try_into()?;
         }
-        let mmap = unsafe { Mmap::map(&file).expect(&format!("Error mapping file {}", path)) };
-        let mut reader = Cursor::new(mmap);
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::parse(&mmap, file_timestamp)
+    }
+
+    // parses a catalog that isn't a file on disk, e.g. one read out of an
+    // archive, the catalog starts at the current position of reader
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<CarUtilAssetStorage, CarError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    // the catalog is parsed in place, nothing borrows data afterwards
+    pub fn from_bytes(data: &[u8]) -> Result<CarUtilAssetStorage, CarError> {
+        if data.is_empty() {
+            return Err(CarError::Truncated);
+        }
+        Ok(Self::parse(data, 0)?)
+    }
+
+    // parses every catalog of a file made of several concatenated catalogs,
+    // each one starting with the BOM magic
+    pub fn read_all<R: Read + Seek>(reader: &mut R) -> Result<Vec<CarUtilAssetStorage>, CarError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        let mut catalogs = vec![];
+        let mut start = 0;
+        while start < data.len() {
+            let extent = bom::Storage::read(&mut Cursor::new(&data[start..]))?.extent() as usize;
+            catalogs.push(Self::from_bytes(&data[start..])?);
+
+            // catalogs may be padded, look for the next magic past this one
+            start += extent.max(1);
+            match data[start.min(data.len())..]
+                .windows(8)
+                .position(|window| window == b"BOMStore")
+            {
+                Some(offset) => start += offset,
+                None => break,
+            }
+        }
+        Ok(catalogs)
+    }
+
+    fn parse(data: &[u8], file_timestamp: u32) -> Result<CarUtilAssetStorage> {
+        let mut reader = Cursor::new(data);
 
         // read items from bom storage
         let bom_storage = bom::Storage::read(&mut reader)?;
-        let mut car_header =
-            bom_storage.get_named_typed_block::<CarHeader>("CARHEADER", &mut reader, ())?;
+        let car_header_range = bom_storage.get_named_block("CARHEADER")?;
+        reader.set_position(car_header_range.address as u64);
+        // the other blocks, key format and CSI headers are read with the
+        // endianness of the header, BOM structures are always big endian
+        let endian = CarHeader::detect_endian(&mut reader)?;
+        let mut car_header = CarHeader::read_options(&mut reader, endian, ())?;
 
         if car_header.storage_timestamp == 0 {
             // default to file timestamp if the Assets.car file doesn't have a timestamp
             car_header.storage_timestamp = file_timestamp;
         }
 
-        let extended_metadata = bom_storage.get_named_typed_block::<CarExtendedMetadata>(
-            "EXTENDED_METADATA",
-            &mut reader,
-            (),
-        )?;
-        let renditionkeyfmt = bom_storage.get_named_typed_block::<rendition::KeyFormat>(
-            "KEYFORMAT",
-            &mut reader,
-            (),
-        )?;
-
-        let facetkeys_tree =
-            bom_storage.get_named_typed_block::<bom::Tree>("FACETKEYS", &mut reader, ())?;
-        let facetkeys = facetkeys_tree
-            .items_typed::<NullString, rendition::KeyToken>(&bom_storage, &mut reader)?;
-        let facetkeysdb = facetkeys
-            .into_iter()
-            .map(|(name, token)| (name.to_string(), token))
-            .collect();
+        let extended_metadata_range = bom_storage.get_named_block("EXTENDED_METADATA")?;
+        reader.set_position(extended_metadata_range.address as u64);
+        let extended_metadata = CarExtendedMetadata::read_options(&mut reader, endian, ())?;
+        // parsed from its own block so max_count can be checked against it
+        let key_format_data = bom_storage
+            .get_named_block("KEYFORMAT")?
+            .read(&mut reader)?;
+        let renditionkeyfmt =
+            rendition::KeyFormat::read_options(&mut Cursor::new(key_format_data), endian, ())?;
+
+        let facetkeysdb =
+            read_facet_keys(car_header.schema_version, &bom_storage, &mut reader, endian)?;
 
         let bitmapkeys: Option<Vec<(NameIdentifier, bitmap::Key)>> = bom_storage
             .get_named_typed_block::<bom::Tree>("BITMAPKEYS", &mut reader, ())
@@ -80,7 +144,7 @@ impl CarUtilAssetStorage {
                         let value_pointer =
                             &bom_storage.block_storage.items[indices.index0 as usize];
                         reader.set_position((value_pointer.address) as u64);
-                        let value = bitmap::Key::read(&mut reader)?;
+                        let value = bitmap::Key::read_options(&mut reader, endian, ())?;
                         Ok((key, value))
                     })
                     .into_iter()
@@ -88,7 +152,7 @@ impl CarUtilAssetStorage {
             })
             .ok();
 
-        let rendition_sha_digests: BTreeMap<rendition::Key, Vec<u8>> = bom_storage
+        let renditions: Vec<(rendition::Key, bom::BlockRange, Vec<u8>)> = bom_storage
             .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
             .and_then(|tree| {
                 let path_range = bom_storage.block_storage.items[tree.path_block_id as usize];
@@ -97,29 +161,35 @@ impl CarUtilAssetStorage {
                 path.indices
                     .into_iter()
                     .map(|indices| {
-                        let mut key_range =
-                            bom_storage.block_storage.items[indices.index1 as usize];
-                        key_range.length = 36; // sometimes this is less? rendition key needs exactly 36 bytes
-                        let key = key_range
-                            .read_type::<rendition::Key>(&mut reader, ())
-                            .unwrap();
-                        let value_range = &bom_storage.block_storage.items[indices.index0 as usize];
+                        // sometimes the block is less? a rendition key is always 36 bytes
+                        let key_range = bom_storage.block_storage.items[indices.index1 as usize];
+                        reader.set_position(key_range.address as u64);
+                        let key = rendition::Key::read_options(&mut reader, endian, ())?;
+                        let value_range = bom_storage.block_storage.items[indices.index0 as usize];
                         let value = value_range.read(&mut reader)?;
                         let mut hasher = Sha256::new();
                         hasher.update(value);
-                        Ok((key, hasher.finalize().to_vec()))
+                        Ok((key, value_range, hasher.finalize().to_vec()))
                     })
                     .into_iter()
                     .collect()
             })
-            .expect("Unable to find required RENDITIONS var in BOMTree.");
+            .context("Unable to find required RENDITIONS var in BOMTree.")?;
+        let rendition_blocks: BTreeMap<rendition::Key, bom::BlockRange> = renditions
+            .iter()
+            .map(|(key, block_range, _)| (*key, *block_range))
+            .collect();
+        let rendition_sha_digests: BTreeMap<rendition::Key, Vec<u8>> = renditions
+            .into_iter()
+            .map(|(key, _, digest)| (key, digest))
+            .collect();
 
         let imagedb: BTreeMap<rendition::Key, csi::Header> = bom_storage
             .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
             .and_then(|tree| {
-                tree.items_typed::<rendition::Key, csi::Header>(&bom_storage, &mut reader)
+                tree.items_typed::<rendition::Key, csi::Header>(&bom_storage, &mut reader, endian)
             })
-            .expect("Unable to find required RENDITIONS var in BOMTree.")
+            .context("Unable to find required RENDITIONS var in BOMTree.")?
             .into_iter()
             .collect();
 
@@ -134,7 +204,7 @@ impl CarUtilAssetStorage {
                     .map(|indices| {
                         let key_range = &bom_storage.block_storage.items[indices.index0 as usize];
                         reader.set_position((key_range.address) as u64);
-                        let key = <u32>::read_le(&mut reader)?;
+                        let key = <u32>::read_options(&mut reader, endian, ())?;
 
                         let value_range = &bom_storage.block_storage.items[indices.index1 as usize];
                         let value = value_range.read(&mut reader)?;
@@ -152,15 +222,48 @@ impl CarUtilAssetStorage {
             extended_metadata,
             renditionkeyfmt,
             rendition_sha_digests,
+            rendition_blocks,
             appearancedb,
             facetkeysdb,
             bitmapkeydb,
             imagedb,
+            name_index: NameIndex::default(),
         };
         let theme_store = StructuredThemeStore { store };
         Ok(CarUtilAssetStorage { theme_store })
     }
 
+    pub fn raw_header(&self) -> &CarHeader {
+        &self.theme_store.store.header
+    }
+
+    pub fn raw_extended_metadata(&self) -> &CarExtendedMetadata {
+        &self.theme_store.store.extended_metadata
+    }
+
+    // fields of the EXTENDED_METADATA block, looked up by name in the BOM
+    pub fn thinning_arguments(&self) -> String {
+        self.theme_store.store.thinning_arguments()
+    }
+
+    pub fn deployment_platform(&self) -> String {
+        self.theme_store.store.deployment_platform()
+    }
+
+    pub fn deployment_platform_version(&self) -> String {
+        self.theme_store.store.deployment_platform_version()
+    }
+
+    pub fn authoring_tool(&self) -> String {
+        self.theme_store.store.authoring_tool()
+    }
+
+    // stored as is, it doesn't cover the file (neither CRC-32 nor Adler-32 of
+    // the header or the whole file match) so it can't be verified
+    pub fn associated_checksum(&self) -> u32 {
+        self.theme_store.store.header.associated_checksum
+    }
+
     pub fn write_data(&self, path: &str) -> Result<()> {
         let mut buffer: Vec<u8> = vec![];
         let mut writer = Cursor::new(&mut buffer);
@@ -169,7 +272,7 @@ impl CarUtilAssetStorage {
         // header
         let next_address = block_storage.next_item_address();
         writer.set_position(next_address as u64);
-        self.theme_store.store.header.write(&mut writer)?;
+        self.theme_store.store.header.write_le(&mut writer)?;
         let header_block_id = block_storage.add_item(next_address, writer.position() as u32);
 
         // extended header
@@ -178,14 +281,17 @@ impl CarUtilAssetStorage {
         self.theme_store
             .store
             .extended_metadata
-            .write(&mut writer)?;
+            .write_le(&mut writer)?;
         let extended_header_block_id =
             block_storage.add_item(next_address, writer.position() as u32);
 
         // rendition key fmt
         let next_address = block_storage.next_item_address();
         writer.set_position(next_address as u64);
-        self.theme_store.store.renditionkeyfmt.write(&mut writer)?;
+        self.theme_store
+            .store
+            .renditionkeyfmt
+            .write_le(&mut writer)?;
         let rendition_key_format_block_id =
             block_storage.add_item(next_address, writer.position() as u32);
 
@@ -194,12 +300,12 @@ impl CarUtilAssetStorage {
         for (rendition_key, csi_header) in &self.theme_store.store.imagedb {
             let next_address = block_storage.next_item_address();
             writer.set_position(next_address as u64);
-            rendition_key.write(&mut writer)?;
+            rendition_key.write_le(&mut writer)?;
             let key_block_id = block_storage.add_item(next_address, writer.position() as u32);
 
             let next_address = block_storage.next_item_address();
             writer.set_position(next_address as u64);
-            csi_header.write(&mut writer)?;
+            csi_header.write_le(&mut writer)?;
             let value_block_id = block_storage.add_item(next_address, writer.position() as u32);
 
             rendition_path_indices.push(bom::PathIndices {
@@ -300,7 +406,10 @@ pub struct CommonAssetStorage {
     pub header: CarHeader,                      // CARHEADER
     pub extended_metadata: CarExtendedMetadata, // EXTENDED_METADATA
     pub renditionkeyfmt: rendition::KeyFormat,  // KEYFORMAT
+    // catalogs store no digest, these are taken from the renditions when read
     pub rendition_sha_digests: BTreeMap<rendition::Key, Vec<u8>>,
+    // where each rendition is stored in the catalog
+    pub rendition_blocks: BTreeMap<rendition::Key, bom::BlockRange>,
 
     pub imagedb: BTreeMap<rendition::Key, csi::Header>, // RENDITIONS
     // pub colordb: Option<Vec<db::Entry<Color>>>,
@@ -311,24 +420,80 @@ pub struct CommonAssetStorage {
     pub facetkeysdb: Vec<(String, rendition::KeyToken)>, // FACETKEYS
     pub bitmapkeydb: Option<Vec<(NameIdentifier, bitmap::Key)>>, // BITMAPKEYS
     pub appearancedb: Option<BTreeMap<String, u32>>,     // APPEARANCEKEYS
+    pub name_index: NameIndex,
+}
+
+// which renditions CommonAssetStorage::filter returns, unset attributes match
+// any value
+#[derive(Debug, Default, Clone)]
+pub struct RenditionFilter {
+    pub idiom: Option<rendition::Idiom>,
+    pub scale: Option<u32>,
+    pub appearance: Option<String>,
+}
+
+impl RenditionFilter {
+    pub fn idiom(mut self, idiom: rendition::Idiom) -> Self {
+        self.idiom = Some(idiom);
+        self
+    }
+
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    // appearance name, e.g. UIAppearanceDark
+    pub fn appearance(mut self, appearance: &str) -> Self {
+        self.appearance = Some(appearance.to_string());
+        self
+    }
+}
+
+// rendition keys by facet name and by rendition name, built on the first
+// lookup so changes made to imagedb or facetkeysdb afterwards aren't seen
+#[derive(Debug, Default)]
+pub struct NameIndex(OnceLock<NameIndexMaps>);
+
+#[derive(Debug)]
+struct NameIndexMaps {
+    facets: HashMap<String, Vec<rendition::Key>>,
+    renditions: HashMap<String, rendition::Key>,
 }
 
 impl CommonAssetStorage {
     pub fn thinning_arguments(&self) -> String {
-        common::parse_padded_string(&self.extended_metadata.thinning_arguments)
+        common::parse_space_padded_string(&self.extended_metadata.thinning_arguments)
     }
     pub fn deployment_platform_version(&self) -> String {
-        common::parse_padded_string(&self.extended_metadata.deployment_platform_version)
+        common::parse_space_padded_string(&self.extended_metadata.deployment_platform_version)
     }
     pub fn deployment_platform(&self) -> String {
-        common::parse_padded_string(&self.extended_metadata.deployment_platform)
+        common::parse_space_padded_string(&self.extended_metadata.deployment_platform)
     }
     pub fn authoring_tool(&self) -> String {
-        common::parse_padded_string(&self.extended_metadata.authoring_tool)
+        common::parse_space_padded_string(&self.extended_metadata.authoring_tool)
     }
     pub fn version_string(&self) -> String {
         common::parse_padded_string(&self.header.version_string)
     }
+    // canonical 8-4-4-4-12 form, uppercase like NSUUID
+    pub fn uuid_string(&self) -> String {
+        let hex: String = self
+            .header
+            .uuid
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
     pub fn main_version_string(&self) -> String {
         common::parse_padded_string(&self.header.main_version_string)
     }
@@ -337,10 +502,696 @@ impl CommonAssetStorage {
             .clone()
             .and_then(|appearances| Some(appearances.into_iter().collect()))
     }
+
+    // when actool built the catalog, from the header timestamp
+    pub fn build_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.header.storage_timestamp as u64)
+    }
+
+    // time elapsed since the catalog was built, 0 if built in the future
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.build_time())
+            .unwrap_or_default()
+    }
+
+    // estimated number of bytes needed if every image was decoded to RGBA
+    pub fn decoded_footprint(&self) -> u64 {
+        self.imagedb
+            .values()
+            .filter(|csi_header| {
+                matches!(
+                    csi_header.csimetadata.layout,
+                    rendition::LayoutType32::Image | rendition::LayoutType32::PackedImage
+                )
+            })
+            .map(|csi_header| {
+                let width = csi_header.pixel_width().unwrap_or(0) as u64;
+                let height = csi_header.pixel_height().unwrap_or(0) as u64;
+                width * height * 4
+            })
+            .sum()
+    }
+
+    // distinct DeploymentTarget attributes across all renditions as platform
+    // version identifiers, e.g. "ios13.0", renditions without one target the
+    // catalog's own deployment version
+    pub fn deployment_targets(&self) -> Vec<String> {
+        let deployment_targets: BTreeSet<u16> = self
+            .imagedb
+            .keys()
+            .filter_map(|rendition_key| {
+                rendition_key.find_attribute(
+                    &self.renditionkeyfmt,
+                    rendition::AttributeType::DeploymentTarget,
+                )
+            })
+            .collect();
+        let platform = self.deployment_platform();
+        deployment_targets
+            .into_iter()
+            .map(|deployment_target| {
+                match deployment_target_version(&platform, deployment_target) {
+                    Some(version) => format!("{}{}", platform, version),
+                    None if deployment_target == 0 => {
+                        format!("{}{}", platform, self.deployment_platform_version())
+                    }
+                    None => format!("{} ({})", platform, deployment_target),
+                }
+            })
+            .collect()
+    }
+
+    // all attributes of the facet key token for name
+    pub fn facet_attributes(&self, name: &str) -> Option<Vec<(rendition::AttributeType, u16)>> {
+        self.facetkeysdb
+            .iter()
+            .find(|(facet_name, _)| facet_name == name)
+            .map(|(_, key_token)| {
+                key_token
+                    .attributes
+                    .iter()
+                    .filter_map(|attribute| {
+                        let attribute_type: Option<rendition::AttributeType> =
+                            FromPrimitive::from_u16(attribute.name as u16);
+                        attribute_type.map(|attribute_type| (attribute_type, attribute.value))
+                    })
+                    .collect()
+            })
+    }
+
+    // name for an Appearance attribute value, 0 is the default appearance
+    pub fn appearance_name(&self, appearance: u16) -> String {
+        self.appearancedb
+            .as_ref()
+            .and_then(|appearancedb| {
+                appearancedb
+                    .iter()
+                    .find(|(_, appearance_index)| **appearance_index == appearance as u32)
+                    .map(|(appearance_name, _)| appearance_name.to_string())
+            })
+            .unwrap_or_else(|| match appearance {
+                0 => "UIAppearanceAny".to_string(),
+                _ => appearance.to_string(),
+            })
+    }
+
+    // appearance names keyed by the id used in rendition keys
+    pub fn appearance_names(&self) -> HashMap<u16, String> {
+        self.appearancedb
+            .iter()
+            .flatten()
+            .map(|(appearance_name, appearance)| (*appearance as u16, appearance_name.clone()))
+            .collect()
+    }
+
+    // true if any rendition is specific to an appearance other than Any
+    pub fn supports_dark_mode(&self) -> bool {
+        self.imagedb.keys().any(|rendition_key| {
+            let appearance = rendition_key
+                .find_attribute(&self.renditionkeyfmt, rendition::AttributeType::Appearance);
+            match appearance {
+                Some(appearance) => self.appearance_name(appearance) != "UIAppearanceAny",
+                None => false,
+            }
+        })
+    }
+
+    // name identifier of the facet named name
+    pub fn name_identifier(&self, name: &str) -> Option<u16> {
+        self.facetkeysdb
+            .iter()
+            .find(|(facet_name, _)| facet_name == name)
+            .and_then(|(_, key_token)| {
+                key_token
+                    .attributes
+                    .iter()
+                    .find(|attribute| attribute.name == rendition::AttributeType16::Identifier)
+                    .map(|attribute| attribute.value)
+            })
+    }
+
+    // all renditions belonging to the facet named name
+    pub fn renditions_for_name(&self, name: &str) -> Vec<(&rendition::Key, &csi::Header)> {
+        let name_identifier = match self.name_identifier(name) {
+            Some(name_identifier) => name_identifier,
+            None => return vec![],
+        };
+        self.imagedb
+            .iter()
+            .filter(|(rendition_key, _)| {
+                rendition_key
+                    .find_attribute(&self.renditionkeyfmt, rendition::AttributeType::Identifier)
+                    == Some(name_identifier)
+            })
+            .collect()
+    }
+
+    // picks the rendition CoreUI would use: the requested appearance over the
+    // default (Any) one, the requested idiom over universal, then the requested
+    // scale over the largest one
+    pub fn best_rendition(
+        &self,
+        name: &str,
+        idiom: rendition::Idiom,
+        scale: u32,
+        appearance: Option<&str>,
+    ) -> Option<&csi::Header> {
+        let idiom = idiom as u16;
+        let appearance = appearance.and_then(|appearance| {
+            self.appearancedb
+                .as_ref()?
+                .get(appearance)
+                .map(|appearance_index| *appearance_index as u16)
+        });
+        self.renditions_for_name(name)
+            .into_iter()
+            .filter_map(|(rendition_key, csi_header)| {
+                let attribute = |attribute: rendition::AttributeType| {
+                    rendition_key
+                        .find_attribute(&self.renditionkeyfmt, attribute)
+                        .unwrap_or(0)
+                };
+                let rendition_appearance = attribute(rendition::AttributeType::Appearance);
+                let rendition_idiom = attribute(rendition::AttributeType::Idiom);
+                let rendition_scale = attribute(rendition::AttributeType::Scale) as u32;
+                if rendition_appearance != 0 && Some(rendition_appearance) != appearance {
+                    return None;
+                }
+                if rendition_idiom != 0 && rendition_idiom != idiom {
+                    return None;
+                }
+                let preference = (
+                    rendition_appearance != 0,
+                    rendition_idiom == idiom,
+                    rendition_scale == scale,
+                    rendition_scale,
+                );
+                Some((preference, csi_header))
+            })
+            .max_by_key(|(preference, _)| *preference)
+            .map(|(_, csi_header)| csi_header)
+    }
+
+    // PNG bytes of the best image for name, None if there is no such image
+    pub fn get_image_data(
+        &self,
+        name: &str,
+        scale: u32,
+        idiom: rendition::Idiom,
+    ) -> Result<Option<Vec<u8>>> {
+        let csi_header = match self.best_rendition(name, idiom, scale, None) {
+            Some(csi_header) => csi_header,
+            None => return Ok(None),
+        };
+        let mut png_data = vec![];
+        csi_header.write_png(&mut png_data)?;
+        Ok(Some(png_data))
+    }
+
+    pub fn facet(&self, name: &str) -> Option<Facet> {
+        self.facetkeysdb
+            .iter()
+            .find(|(facet_name, _)| facet_name == name)
+            .map(|(facet_name, _)| Facet {
+                name: facet_name,
+                renditions: self.renditions_for_name(facet_name),
+                asset_storage: self,
+            })
+    }
+
+    pub fn facets(&self) -> Vec<Facet> {
+        self.facetkeysdb
+            .iter()
+            .map(|(facet_name, _)| Facet {
+                name: facet_name,
+                renditions: self.renditions_for_name(facet_name),
+                asset_storage: self,
+            })
+            .collect()
+    }
+
+    // decodes one image per facet and packs them into a single atlas, facets
+    // without a decodable image are skipped
+    pub fn export_sprite_sheet(
+        &self,
+        options: &SpriteSheetOptions,
+    ) -> Result<(SpriteSheet, SpriteMap)> {
+        let mut sprites = vec![];
+        for facet in self.facets() {
+            let images = facet.renditions.iter().filter(|(_, csi_header)| {
+                csi_header.csimetadata.layout == rendition::LayoutType32::Image
+            });
+            let scale_of = |rendition_key: &rendition::Key| {
+                rendition_key
+                    .find_attribute(&self.renditionkeyfmt, rendition::AttributeType::Scale)
+                    .unwrap_or(0)
+            };
+            let csi_header = match images
+                .clone()
+                .find(|(rendition_key, _)| scale_of(rendition_key) == options.scale)
+                .or_else(|| images.max_by_key(|(rendition_key, _)| scale_of(rendition_key)))
+            {
+                Some((_, csi_header)) => csi_header,
+                None => continue,
+            };
+            if csi_header.width == 0 || csi_header.height == 0 {
+                continue;
+            }
+            if let Ok(pixels) = csi_header.decoded_pixels() {
+                sprites.push(Sprite {
+                    name: facet.name.to_string(),
+                    width: csi_header.width,
+                    height: csi_header.height,
+                    pixels,
+                });
+            }
+        }
+        Ok(SpriteSheet::pack(sprites, options)?)
+    }
+
+    // image facets lacking some of the expected scales, with the missing ones
+    pub fn missing_scales(&self, expected: &[u32]) -> Vec<(String, Vec<u32>)> {
+        self.facets()
+            .into_iter()
+            .filter(|facet| {
+                facet.renditions.iter().any(|(_, csi_header)| {
+                    csi_header.csimetadata.layout == rendition::LayoutType32::Image
+                })
+            })
+            .filter_map(|facet| {
+                let available_scales = facet.available_scales();
+                let missing: Vec<u32> = expected
+                    .iter()
+                    .filter(|scale| !available_scales.contains(scale))
+                    .cloned()
+                    .collect();
+                if missing.is_empty() {
+                    None
+                } else {
+                    Some((facet.name.to_string(), missing))
+                }
+            })
+            .collect()
+    }
+
+    // resolves the sizes of a multisize image set to the images of the same
+    // facet, sizes are matched on the Dimension1 attribute and the idiom
+    pub fn multisize_image_set_members(&self, name: &str) -> Vec<MultisizeImageSetMember> {
+        let renditions = self.renditions_for_name(name);
+        let attribute = |rendition_key: &rendition::Key,
+                         attribute_type: rendition::AttributeType| {
+            rendition_key
+                .find_attribute(&self.renditionkeyfmt, attribute_type)
+                .unwrap_or(0)
+        };
+        let mut members = vec![];
+        for (_, csi_header) in &renditions {
+            let entries = match csi_header.multisize_image_set_entries() {
+                Some(entries) => entries,
+                None => continue,
+            };
+            for entry in entries {
+                let mut images: Vec<(&rendition::Key, &csi::Header)> = renditions
+                    .iter()
+                    .filter(|(rendition_key, csi_header)| {
+                        csi_header.csimetadata.layout != rendition::LayoutType32::MultisizeImage
+                            && attribute(rendition_key, rendition::AttributeType::Dimension1)
+                                == entry.index
+                            && attribute(rendition_key, rendition::AttributeType::Idiom)
+                                == entry.idiom.clone() as u16
+                    })
+                    .copied()
+                    .collect();
+                images.sort_by_key(|(rendition_key, _)| {
+                    attribute(rendition_key, rendition::AttributeType::Scale)
+                });
+                for (rendition_key, image) in images {
+                    members.push(MultisizeImageSetMember {
+                        width: entry.width,
+                        height: entry.height,
+                        index: entry.index,
+                        idiom: entry.idiom.clone(),
+                        scale: attribute(rendition_key, rendition::AttributeType::Scale),
+                        rendition_key,
+                        csi_header: image,
+                    });
+                }
+            }
+        }
+        members
+    }
+
+    // rendition a link of an InternalReference or LayerReference rendition
+    // points at
+    pub fn resolve_link(
+        &self,
+        rendition_link: &rendition::RenditionLink,
+    ) -> Option<(&rendition::Key, &csi::Header)> {
+        self.imagedb
+            .get_key_value(&self.renditionkeyfmt.key_for_token(&rendition_link.key))
+    }
+
+    // collects the renditions making up a symbol, the links of its
+    // ContentRendition and LayerReference renditions are followed to the
+    // renditions holding the content
+    pub fn resolve_symbol(&self, name: &str) -> Option<SymbolComposition> {
+        let layers: Vec<SymbolLayer> = self
+            .renditions_for_name(name)
+            .into_iter()
+            .filter(|(_, csi_header)| {
+                matches!(
+                    csi_header.csimetadata.layout,
+                    rendition::LayoutType32::ContentRendition
+                        | rendition::LayoutType32::LayerReference
+                        | rendition::LayoutType32::Vector
+                )
+            })
+            .map(|(rendition_key, csi_header)| SymbolLayer {
+                rendition_key: *rendition_key,
+                layout: csi_header.csimetadata.layout,
+                name: csi_header.csimetadata.name(),
+                frame: csi_header
+                    .rendition_link()
+                    .map(|rendition_link| rendition_link.frame()),
+                references: self.link_chain(rendition_key, csi_header),
+            })
+            .collect();
+        if layers.is_empty() {
+            None
+        } else {
+            Some(SymbolComposition {
+                name: name.to_string(),
+                layers,
+            })
+        }
+    }
+
+    // keys of the renditions reached by following links from a rendition, a
+    // link that can't be resolved or loops back ends the chain
+    fn link_chain(
+        &self,
+        rendition_key: &rendition::Key,
+        csi_header: &csi::Header,
+    ) -> Vec<rendition::Key> {
+        let mut chain = vec![];
+        let mut rendition_link = csi_header.rendition_link();
+        while let Some(link) = rendition_link {
+            match self.resolve_link(link) {
+                Some((linked_key, linked))
+                    if linked_key != rendition_key && !chain.contains(linked_key) =>
+                {
+                    chain.push(*linked_key);
+                    rendition_link = linked.rendition_link();
+                }
+                _ => break,
+            }
+        }
+        chain
+    }
+
+    // renditions linked from the payload of a LayerStack rendition, in
+    // drawing order. layers that can't be resolved are left out
+    pub fn layer_stack_layers(
+        &self,
+        rendition_key: &rendition::Key,
+    ) -> Vec<(&rendition::Key, &csi::Header)> {
+        self.imagedb
+            .get(rendition_key)
+            .and_then(|csi_header| csi_header.layer_stack_layers())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|layer| self.resolve_link(layer))
+            .collect()
+    }
+
+    fn name_index_maps(&self) -> &NameIndexMaps {
+        self.name_index.0.get_or_init(|| {
+            let mut facets = HashMap::new();
+            for (facet_name, _) in &self.facetkeysdb {
+                facets.entry(facet_name.clone()).or_insert_with(|| {
+                    self.renditions_for_name(facet_name)
+                        .into_iter()
+                        .map(|(rendition_key, _)| *rendition_key)
+                        .collect()
+                });
+            }
+            let mut renditions = HashMap::new();
+            for (rendition_key, csi_header) in &self.imagedb {
+                renditions
+                    .entry(csi_header.csimetadata.name())
+                    .or_insert(*rendition_key);
+            }
+            NameIndexMaps { facets, renditions }
+        })
+    }
+
+    // every rendition of the facet named name, e.g. all of its scales
+    pub fn asset_by_name(&self, name: &str) -> Vec<&csi::Header> {
+        match self.name_index_maps().facets.get(name) {
+            Some(rendition_keys) => rendition_keys
+                .iter()
+                .filter_map(|rendition_key| self.imagedb.get(rendition_key))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    // rendition by its own name, e.g. "Timac@2x.png"
+    pub fn rendition_by_name(&self, rendition_name: &str) -> Option<&csi::Header> {
+        self.name_index_maps()
+            .renditions
+            .get(rendition_name)
+            .and_then(|rendition_key| self.imagedb.get(rendition_key))
+    }
+
+    // renditions whose key matches every attribute set in filter, renditions
+    // without an attribute have the default value for it
+    pub fn filter(&self, filter: RenditionFilter) -> Vec<&csi::Header> {
+        self.imagedb
+            .iter()
+            .filter(|(rendition_key, _)| {
+                let attributes = self.renditionkeyfmt.map(rendition_key);
+                let attribute = |attribute_type| {
+                    attributes
+                        .iter()
+                        .find(|(attribute, _)| *attribute == attribute_type)
+                        .map(|(_, value)| *value)
+                        .unwrap_or(0)
+                };
+                if let Some(idiom) = &filter.idiom {
+                    if attribute(rendition::AttributeType::Idiom) != idiom.clone() as u16 {
+                        return false;
+                    }
+                }
+                if let Some(scale) = filter.scale {
+                    if attribute(rendition::AttributeType::Scale) as u32 != scale {
+                        return false;
+                    }
+                }
+                if let Some(appearance) = &filter.appearance {
+                    if self.appearance_name(attribute(rendition::AttributeType::Appearance))
+                        != *appearance
+                    {
+                        return false;
+                    }
+                }
+                true
+            })
+            .map(|(_, csi_header)| csi_header)
+            .collect()
+    }
+
+    // renditions in key order, without moving them out of imagedb
+    pub fn iter(&self) -> impl Iterator<Item = (&rendition::Key, &csi::Header)> {
+        self.imagedb.iter()
+    }
+
+    // number of renditions
+    pub fn len(&self) -> usize {
+        self.imagedb.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.imagedb.is_empty()
+    }
+
+    pub fn renditions_by_layout(&self, layout: rendition::LayoutType32) -> Vec<&csi::Header> {
+        self.imagedb
+            .values()
+            .filter(|csi_header| csi_header.csimetadata.layout == layout)
+            .collect()
+    }
+
+    // number of renditions for each layout type
+    pub fn layout_histogram(&self) -> HashMap<rendition::LayoutType32, usize> {
+        let mut histogram = HashMap::new();
+        for csi_header in self.imagedb.values() {
+            *histogram.entry(csi_header.csimetadata.layout).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    // catalogs carry no per-rendition digest, so the rendition block is read
+    // again from catalog (the bytes of the file the storage was read from) and
+    // compared with the digest taken when reading it. The digest covers the
+    // CSI bytes as stored in the RENDITIONS tree: the 184 byte header, the TLVs
+    // and the rendition data
+    pub fn verify_digest(
+        &self,
+        catalog: &[u8],
+        rendition_key: &rendition::Key,
+    ) -> Result<bool, CarError> {
+        let (block_range, digest) = match (
+            self.rendition_blocks.get(rendition_key),
+            self.rendition_sha_digests.get(rendition_key),
+        ) {
+            (Some(block_range), Some(digest)) => (block_range, digest),
+            _ => return Ok(false),
+        };
+        let start = block_range.address as usize;
+        let csi_bytes = catalog
+            .get(start..start + block_range.length as usize)
+            .ok_or(CarError::Truncated)?;
+        Ok(Sha256::digest(csi_bytes).as_slice() == digest.as_slice())
+    }
+
+    // number of renditions for each compression type, renditions without a
+    // compressed payload (colors, references) aren't counted
+    pub fn compression_histogram(&self) -> HashMap<rendition::CompressionType, usize> {
+        let mut histogram = HashMap::new();
+        for compression_type in self
+            .imagedb
+            .values()
+            .filter_map(|csi_header| csi_header.compression_type())
+        {
+            *histogram.entry(compression_type).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    // occurrences of each TLV tag across all renditions, including unknown ones
+    pub fn tlv_tag_histogram(&self) -> HashMap<u32, usize> {
+        let mut histogram = HashMap::new();
+        for csi_header in self.imagedb.values() {
+            let mut cursor = Cursor::new(csi_header.tlv_bytes());
+            let endian = csi_header.endian;
+            while let (Ok(tag), Ok(length)) = (
+                u32::read_options(&mut cursor, endian, ()),
+                u32::read_options(&mut cursor, endian, ()),
+            ) {
+                *histogram.entry(tag).or_insert(0) += 1;
+                cursor.set_position(cursor.position() + length as u64);
+            }
+        }
+        histogram
+    }
+
+    // checks for inconsistencies in the catalog
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut identifiers_by_name: BTreeMap<&str, BTreeSet<u16>> = BTreeMap::new();
+        for (name, key_token) in &self.facetkeysdb {
+            if let Some(attribute) = key_token
+                .attributes
+                .iter()
+                .find(|attribute| attribute.name == rendition::AttributeType16::Identifier)
+            {
+                identifiers_by_name
+                    .entry(name)
+                    .or_default()
+                    .insert(attribute.value);
+            }
+        }
+
+        identifiers_by_name
+            .into_iter()
+            .filter(|(_, identifiers)| identifiers.len() > 1)
+            .map(|(name, identifiers)| ValidationIssue::DuplicateFacetName {
+                name: name.to_string(),
+                identifiers: identifiers.into_iter().collect(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct SymbolComposition {
+    pub name: String,
+    pub layers: Vec<SymbolLayer>,
+}
+
+#[derive(Debug)]
+pub struct SymbolLayer {
+    pub rendition_key: rendition::Key,
+    pub layout: rendition::LayoutType32,
+    pub name: String,
+    // where the linked rendition is placed, None for renditions holding
+    // their content themselves
+    pub frame: Option<SpriteRect>,
+    // renditions the links lead to, the last one holds the content
+    pub references: Vec<rendition::Key>,
+}
+
+// one size of a multisize image set, resolved to an image rendition
+#[derive(Debug)]
+pub struct MultisizeImageSetMember<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub index: u16,
+    pub idiom: rendition::Idiom,
+    pub scale: u16,
+    pub rendition_key: &'a rendition::Key,
+    pub csi_header: &'a csi::Header,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationIssue {
+    // the same facet name maps to several name identifiers
+    DuplicateFacetName { name: String, identifiers: Vec<u16> },
 }
 
+// newest schema whose facet keys are known to be stored as key tokens
+const KEY_TOKEN_SCHEMA_VERSION: u32 = 2;
+
+// DeploymentTarget counts the yearly OS releases, 1 is the 2016 one (iOS 10)
+// and 0 is any release
+fn deployment_target_version(platform: &str, deployment_target: u16) -> Option<String> {
+    let release = deployment_target.checked_sub(1)?;
+    match platform {
+        "ios" | "tvos" => Some(format!("{}.0", 10 + release)),
+        "watchos" => Some(format!("{}.0", 3 + release)),
+        "macosx" | "macos" if release < 4 => Some(format!("10.{}", 12 + release)),
+        "macosx" | "macos" => Some(format!("{}.0", 7 + release)),
+        _ => None,
+    }
+}
+
+// newer schemas are tried with the key token layout, a schema whose facet keys
+// don't parse that way is reported as unsupported instead of as a bad token
+fn read_facet_keys(
+    schema_version: u32,
+    bom_storage: &bom::Storage,
+    reader: &mut Cursor<&[u8]>,
+    endian: Endian,
+) -> Result<Vec<(String, rendition::KeyToken)>> {
+    let facetkeys_tree = bom_storage.get_named_typed_block::<bom::Tree>("FACETKEYS", reader, ())?;
+    let facetkeys =
+        facetkeys_tree.items_typed::<NullString, rendition::KeyToken>(bom_storage, reader, endian);
+    let facetkeys = match facetkeys {
+        Ok(facetkeys) => facetkeys,
+        Err(_) if schema_version > KEY_TOKEN_SCHEMA_VERSION => {
+            return Err(CarError::UnsupportedSchema(schema_version).into())
+        }
+        Err(err) => return Err(err),
+    };
+    Ok(facetkeys
+        .into_iter()
+        .map(|(name, token)| (name.to_string(), token))
+        .collect())
+}
+
+// endianness is detected from the magic, see CarHeader::read_with_detected_endian
 #[derive(BinRead, BinWrite)]
-#[brw(little)]
 pub struct CarHeader {
     pub magic: u32,
     pub core_ui_version: u32,
@@ -385,6 +1236,27 @@ impl CarHeader {
             key_semantics,
         }
     }
+
+    // magic reads as "RATC" for little endian catalogs and "CTAR" for big endian
+    // ones, the reader is left at the start of the header
+    pub fn detect_endian<R: Read + Seek>(reader: &mut R) -> BinResult<Endian> {
+        let position = reader.stream_position()?;
+        let magic = <[u8; 4]>::read_le(reader)?;
+        reader.seek(SeekFrom::Start(position))?;
+        match &magic {
+            b"RATC" => Ok(Endian::Little),
+            b"CTAR" => Ok(Endian::Big),
+            _ => Err(binrw::Error::BadMagic {
+                pos: position,
+                found: Box::new(magic),
+            }),
+        }
+    }
+
+    pub fn read_with_detected_endian<R: Read + Seek>(reader: &mut R) -> BinResult<CarHeader> {
+        let endian = Self::detect_endian(reader)?;
+        CarHeader::read_options(reader, endian, ())
+    }
 }
 
 impl Debug for CarHeader {
@@ -413,7 +1285,6 @@ impl Debug for CarHeader {
 }
 
 #[derive(BinRead, BinWrite)]
-#[brw(little)]
 pub struct CarExtendedMetadata {
     pub magic: u32,
     pub thinning_arguments: [u8; 256],
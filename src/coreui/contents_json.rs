@@ -0,0 +1,114 @@
+use serde::Serialize;
+
+use super::rendition;
+
+// Contents.json of an asset catalog folder, fields are in Xcode's key order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContentsJson {
+    pub images: Vec<ImageEntry>,
+    pub info: InfoBlock,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Properties>,
+}
+
+impl ContentsJson {
+    pub fn new(images: Vec<ImageEntry>) -> ContentsJson {
+        ContentsJson {
+            images,
+            info: InfoBlock::default(),
+            properties: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageEntry {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub appearances: Vec<Appearance>,
+    pub filename: String,
+    pub idiom: String,
+    // vectors are used for every scale and have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtype: Option<String>,
+}
+
+pub fn idiom_name(idiom: u16) -> &'static str {
+    let idiom: Option<rendition::Idiom> = num_traits::FromPrimitive::from_u16(idiom);
+    match idiom {
+        Some(rendition::Idiom::Phone) => "iphone",
+        Some(rendition::Idiom::Pad) => "ipad",
+        Some(rendition::Idiom::TV) => "tv",
+        Some(rendition::Idiom::Car) => "car",
+        Some(rendition::Idiom::Watch) => "watch",
+        Some(rendition::Idiom::Marketing) => "ios-marketing",
+        Some(rendition::Idiom::Mac) => "mac",
+        Some(rendition::Idiom::Vision) => "vision",
+        _ => "universal",
+    }
+}
+
+// the Subtype attribute is the height of the screen the image is made for
+pub fn subtype_name(subtype: u16) -> Option<&'static str> {
+    match subtype {
+        568 => Some("retina4"),
+        340 => Some("38mm"),
+        390 => Some("42mm"),
+        394 => Some("40mm"),
+        430 => Some("41mm"),
+        448 => Some("44mm"),
+        484 => Some("45mm"),
+        502 => Some("49mm"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Appearance {
+    pub appearance: String,
+    pub value: String,
+}
+
+impl Appearance {
+    pub fn luminosity(value: &str) -> Appearance {
+        Appearance {
+            appearance: "luminosity".to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    // from the name of an appearance stored in the catalog
+    pub fn from_name(name: &str) -> Option<Appearance> {
+        match name {
+            "UIAppearanceDark" => Some(Appearance::luminosity("dark")),
+            "UIAppearanceLight" => Some(Appearance::luminosity("light")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InfoBlock {
+    pub author: String,
+    pub version: u32,
+}
+
+// info written by Xcode
+impl Default for InfoBlock {
+    fn default() -> Self {
+        InfoBlock {
+            author: "xcode".to_string(),
+            version: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct Properties {
+    #[serde(
+        rename = "preserves-vector-representation",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub preserves_vector_representation: bool,
+}
@@ -1,39 +1,60 @@
 use anyhow::Context;
 use anyhow::Result;
 use binrw::BinRead;
+use binrw::BinResult;
 use binrw::BinWrite;
+use binrw::Endian;
 use chrono::NaiveDateTime;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+#[cfg(feature = "serde")]
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
 use std::io::BufWriter;
 use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
 
 use crate::common;
 use crate::coregraphics;
+use crate::error::CarError;
 
 use super::csi;
 use super::rendition;
 use super::rendition::CompressionType;
+use super::rendition::ResizingMode;
 use super::rendition::TemplateMode;
 use super::tlv;
+use super::SpriteRect;
 
 #[derive(BinRead, BinWrite, Clone)]
-#[brw(little)]
 pub struct Metadata {
     pub mod_time: u32,
+    #[br(map = rendition::LayoutType32::from_u32)]
+    #[bw(map = |layout: &rendition::LayoutType32| layout.to_u32())]
     pub layout: rendition::LayoutType32,
     pub name: [u8; 128],
 }
 
 impl Metadata {
+    // reads up to the first NUL, invalid UTF-8 is replaced
     pub fn name(&self) -> String {
         common::parse_padded_string(&self.name)
     }
+
+    // names filling all 128 bytes have no terminating NUL and may have been cut off
+    pub fn is_name_truncated(&self) -> bool {
+        !self.name.contains(&0)
+    }
 }
 
 impl Debug for Metadata {
@@ -60,8 +81,8 @@ pub struct Bitmap {
 #[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct BitmapList {
     pub tlv_length: u32,
-    pub unknown: u32, // usually 1?
-    pub zero: u32,    // usually 0?
+    pub bitmap_count: u32, // number of bitmaps in the rendition, always 1 so far
+    pub zero: u32,         // usually 0?
     pub rendition_length: u32,
 }
 
@@ -91,6 +112,47 @@ struct cuithemerenditionrenditionflags {
 #[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct RenditionFlags(pub u32);
 
+// how the bitmap of an image is encoded, taken from its pixel format and
+// compression since the bitmapEncoding of the rendition flags isn't recorded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitmapEncoding {
+    None,
+    RGB,
+    Gray,
+    ARGB16,
+    JPEG,
+    HEIF,
+}
+
+impl BitmapEncoding {
+    // whether images with this pixel format are expected to use this encoding
+    pub fn matches(&self, pixel_format: PixelFormat) -> bool {
+        match self {
+            BitmapEncoding::None => true,
+            BitmapEncoding::RGB => matches!(pixel_format, PixelFormat::ARGB),
+            BitmapEncoding::Gray => matches!(pixel_format, PixelFormat::Gray | PixelFormat::Gray16),
+            BitmapEncoding::ARGB16 => {
+                matches!(pixel_format, PixelFormat::ARGB16 | PixelFormat::ARGBHalf)
+            }
+            BitmapEncoding::JPEG => matches!(pixel_format, PixelFormat::JPEG),
+            BitmapEncoding::HEIF => false,
+        }
+    }
+}
+
+impl std::fmt::Display for BitmapEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitmapEncoding::None => write!(f, "None"),
+            BitmapEncoding::RGB => write!(f, "RGB"),
+            BitmapEncoding::Gray => write!(f, "Gray"),
+            BitmapEncoding::ARGB16 => write!(f, "ARGB16"),
+            BitmapEncoding::JPEG => write!(f, "JPEG"),
+            BitmapEncoding::HEIF => write!(f, "HEIF"),
+        }
+    }
+}
+
 impl RenditionFlags {
     pub fn is_vector_based(&self) -> bool {
         self.0 & 1 == 1
@@ -100,6 +162,14 @@ impl RenditionFlags {
         self.0 & 16 == 16
     }
 
+    pub fn is_flippable(&self) -> bool {
+        self.0 & 0x200 == 0x200
+    }
+
+    pub fn is_tintable(&self) -> bool {
+        self.0 & 0x400 == 0x400
+    }
+
     pub fn has_slice_information(&self) -> bool {
         self.0 & 0x2 == 0x2
     }
@@ -108,8 +178,9 @@ impl RenditionFlags {
         self.0 & 0x4 == 0x4
     }
 
-    pub fn resizing_mode(&self) -> u32 {
-        (self.0 >> 3) & 0x3
+    pub fn resizing_mode(&self) -> Option<ResizingMode> {
+        let value = (self.0 >> 3) & 0x3; // 0b...xx000
+        FromPrimitive::from_u32(value)
     }
 
     pub fn template_rendering_mode(&self) -> Option<TemplateMode> {
@@ -118,7 +189,8 @@ impl RenditionFlags {
     }
 }
 
-#[derive(BinRead, BinWrite, Debug, Clone, Copy, Serialize, FromPrimitive)]
+#[derive(BinRead, BinWrite, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[brw(repr(u32))]
 pub enum PixelFormat {
     None = 0,
@@ -126,6 +198,50 @@ pub enum PixelFormat {
     Data = 0x44415441,
     Gray = 0x47413820,
     JPEG = 0x4A504547,
+    // 'RGBW', extended range
+    ARGB16 = 0x52474257,
+    // 'GA16'
+    Gray16 = 0x47413136,
+    // 'RGBH', half floats
+    ARGBHalf = 0x52474248,
+}
+
+// image formats that can be found inside of a Data rendition
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum EmbeddedImageFormat {
+    PNG,
+    JPEG,
+    HEIC,
+    PDF,
+}
+
+impl EmbeddedImageFormat {
+    pub fn from_magic(data: &[u8]) -> Option<EmbeddedImageFormat> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(EmbeddedImageFormat::PNG)
+        } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(EmbeddedImageFormat::JPEG)
+        } else if data.starts_with(b"%PDF") {
+            Some(EmbeddedImageFormat::PDF)
+        } else if data.len() >= 12
+            && &data[4..8] == b"ftyp"
+            && matches!(&data[8..12], b"heic" | b"heix" | b"mif1" | b"msf1")
+        {
+            Some(EmbeddedImageFormat::HEIC)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EmbeddedImageFormat::PNG => "png",
+            EmbeddedImageFormat::JPEG => "jpg",
+            EmbeddedImageFormat::HEIC => "heic",
+            EmbeddedImageFormat::PDF => "pdf",
+        }
+    }
 }
 
 #[derive(BinRead, BinWrite, Debug, Clone)]
@@ -137,10 +253,17 @@ impl ColorModel {
         let value = self.0 & 0xf; // last nibble
         FromPrimitive::from_u32(value)
     }
+
+    // same nibble, unknown ids fall back to sRGB
+    pub fn color_space(&self) -> coregraphics::ColorSpace {
+        coregraphics::ColorSpace::from_id(self.0 & 0xf).unwrap_or(coregraphics::ColorSpace::SRGB)
+    }
 }
 
+// read with the endianness of the catalog, the magic is 'CTSI' as a u32 and
+// reads as "ISTC" in little endian catalogs
 #[derive(BinRead, BinWrite, Debug, Clone)]
-#[brw(little, magic = b"ISTC")]
+#[brw(magic = 0x43545349u32)]
 pub struct Header {
     pub version: u32,
     pub rendition_flags: RenditionFlags,
@@ -153,23 +276,412 @@ pub struct Header {
     pub csibitmaplist: BitmapList,
     #[br(count = csibitmaplist.tlv_length)]
     pub tlv_data: common::RawData,
-    #[brw(if(csibitmaplist.rendition_length > 0))]
+    #[br(
+        parse_with = parse_rendition_data,
+        args_raw = (csibitmaplist.rendition_length, csimetadata.layout)
+    )]
+    #[bw(if(csibitmaplist.rendition_length > 0))]
     pub rendition_data: Option<rendition::Rendition>,
+    // endianness the header was read with, the TLVs are read with it too
+    #[br(parse_with = current_endian)]
+    #[bw(ignore)]
+    pub endian: Endian,
+}
+
+fn current_endian<R: Read + Seek>(_: &mut R, endian: Endian, _: ()) -> BinResult<Endian> {
+    Ok(endian)
+}
+
+// reference layouts point at another rendition, their payload is read on its
+// own (InternalReference, LayerReference, ContentRendition, LayerStack) or
+// skipped (ExternalLink) to stay aligned with whatever follows
+fn parse_rendition_data<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    (rendition_length, layout): (u32, rendition::LayoutType32),
+) -> BinResult<Option<rendition::Rendition>> {
+    if rendition_length == 0 {
+        return Ok(None);
+    }
+    if matches!(
+        layout,
+        rendition::LayoutType32::InternalReference
+            | rendition::LayoutType32::LayerReference
+            | rendition::LayoutType32::ContentRendition
+    ) {
+        let mut payload = vec![0; rendition_length as usize];
+        reader.read_exact(&mut payload)?;
+        // unknown payloads are left out rather than failing the catalog
+        return match rendition::Rendition::read_options(&mut Cursor::new(payload), endian, ()) {
+            Ok(rendition_data @ rendition::Rendition::InternalLink(_)) => Ok(Some(rendition_data)),
+            _ => Ok(None),
+        };
+    }
+    if layout == rendition::LayoutType32::LayerStack {
+        let mut payload = Cursor::new(vec![0; rendition_length as usize]);
+        reader.read_exact(payload.get_mut())?;
+        let mut layers = vec![];
+        while payload.position() < rendition_length as u64 {
+            match rendition::RenditionLink::read_options(&mut payload, endian, ()) {
+                Ok(layer) => layers.push(layer),
+                // unknown payloads are left out rather than failing the catalog
+                Err(_) => return Ok(None),
+            }
+        }
+        return Ok(Some(rendition::Rendition::LayerStack { layers }));
+    }
+    // layouts of newer versions of CoreUI may not hold a Rendition
+    if let rendition::LayoutType32::Unknown(_) = layout {
+        let mut payload = vec![0; rendition_length as usize];
+        reader.read_exact(&mut payload)?;
+        return Ok(rendition::Rendition::read_options(&mut Cursor::new(payload), endian, ()).ok());
+    }
+    if layout == rendition::LayoutType32::ExternalLink {
+        reader.seek(SeekFrom::Current(rendition_length as i64))?;
+        return Ok(None);
+    }
+    let rendition_data = rendition::Rendition::read_options(reader, endian, ())?;
+    match (layout, rendition_data) {
+        // vector renditions hold a PDF
+        (
+            rendition::LayoutType32::Vector,
+            rendition::Rendition::RawData {
+                version,
+                _raw_data_length,
+                raw_data,
+            },
+        ) => Ok(Some(rendition::Rendition::Vector {
+            version,
+            _raw_data_length,
+            pdf_data: raw_data,
+        })),
+        (_, rendition_data) => Ok(Some(rendition_data)),
+    }
 }
 
 impl Header {
+    // raw TLV region, csibitmaplist.tlv_length bytes
+    pub fn tlv_bytes(&self) -> &[u8] {
+        &self.tlv_data.0
+    }
+
     pub fn properties(&self) -> Vec<tlv::RenditionType> {
         let mut result = vec![];
-        let mut cursor = Cursor::new(self.tlv_data.0.as_slice());
-        while let Ok(rendition_type) = tlv::RenditionType::read_le(&mut cursor) {
+        let mut cursor = Cursor::new(self.tlv_bytes());
+        while let Ok(rendition_type) =
+            tlv::RenditionType::read_options(&mut cursor, self.endian, ())
+        {
             result.push(rendition_type);
         }
         result
     }
 
+    // width in pixels, falling back to the slices TLV when the header reports 0
+    pub fn pixel_width(&self) -> Option<u32> {
+        if self.width > 0 {
+            return Some(self.width);
+        }
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Slices { width, .. } => Some(width),
+                _ => None,
+            })
+    }
+
+    // height in pixels, falling back to the slices TLV when the header reports 0
+    pub fn pixel_height(&self) -> Option<u32> {
+        if self.height > 0 {
+            return Some(self.height);
+        }
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Slices { height, .. } => Some(height),
+                _ => None,
+            })
+    }
+
+    // cap insets of a 3 or 9-part image, from its slices TLV
+    pub fn cap_insets(&self) -> Option<coregraphics::EdgeInsets> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Slices {
+                    slice_count,
+                    insets,
+                    ..
+                } if slice_count > 1 => Some(insets),
+                _ => None,
+            })
+    }
+
+    // opacity recorded in the BlendModeAndOpacity TLV
+    pub fn opacity(&self) -> Option<f32> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::BlendModeAndOpacity { opacity, .. } => Some(opacity),
+                _ => None,
+            })
+    }
+
+    // layout frame in pixels: the metrics TLV holds the size before alpha
+    // cropping and the alignment insets, idk1/idk2 being the top left ones and
+    // idk3/idk4 the bottom right ones
+    pub fn framing_rect(&self) -> Option<coregraphics::Rect> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Metrics {
+                    idk1,
+                    idk2,
+                    idk3,
+                    idk4,
+                    height,
+                    width,
+                    ..
+                } => Some(coregraphics::Rect {
+                    origin: coregraphics::Point {
+                        x: idk1 as f64,
+                        y: idk2 as f64,
+                    },
+                    size: coregraphics::Size {
+                        width: width.saturating_sub(idk1.saturating_add(idk3)) as f64,
+                        height: height.saturating_sub(idk2.saturating_add(idk4)) as f64,
+                    },
+                }),
+                _ => None,
+            })
+    }
+
+    // alpha cropped images record the transparent margins removed from them
+    // in the metrics TLV
+    pub fn is_alpha_cropped(&self) -> bool {
+        self.properties()
+            .into_iter()
+            .any(|rendition_type| match rendition_type {
+                tlv::RenditionType::Metrics {
+                    idk1,
+                    idk2,
+                    idk3,
+                    idk4,
+                    ..
+                } => idk1 > 0 || idk2 > 0 || idk3 > 0 || idk4 > 0,
+                _ => false,
+            })
+    }
+
+    pub fn exif_orientation(&self) -> Option<tlv::EXIFOrientationValue> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::EXIFOrientation { orientation, .. } => Some(orientation),
+                _ => None,
+            })
+    }
+
+    // sizes listed by a multisize image set, see
+    // CommonAssetStorage::multisize_image_set_members for the images themselves
+    pub fn multisize_image_set_entries(&self) -> Option<&[rendition::MultisizeImageSetEntry]> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::MultisizeImageSet { entries, .. }) => Some(entries),
+            _ => None,
+        }
+    }
+
+    // blur parameters of a BlurredImage compressed rendition
+    pub fn blurred_image(&self) -> Option<rendition::BlurredImage> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::BlurredImage,
+                raw_data,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type: CompressionType::BlurredImage,
+                raw_data,
+                ..
+            }) => rendition::BlurredImage::read_options(
+                &mut Cursor::new(&raw_data.0),
+                self.endian,
+                (),
+            )
+            .ok(),
+            _ => None,
+        }
+    }
+
+    pub fn recognition_object(&self) -> Option<RecognitionObject<'_>> {
+        if self.csimetadata.layout != rendition::LayoutType32::RecognitionObject {
+            return None;
+        }
+        let object_type =
+            self.properties()
+                .into_iter()
+                .find_map(|rendition_type| match rendition_type {
+                    tlv::RenditionType::UTI { string, .. } => {
+                        Some(common::parse_padded_string(&string))
+                    }
+                    _ => None,
+                });
+        match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. }) => Some(RecognitionObject {
+                name: self.csimetadata.name(),
+                object_type,
+                data: &raw_data.0,
+            }),
+            _ => None,
+        }
+    }
+
+    // PDF kept alongside the raster for "Preserve Vector Data" images. it lives
+    // in the TLVs, so the raster compression doesn't matter. the tag isn't known
+    // yet, so any TLV holding a PDF is used
+    pub fn preserved_vector_pdf(&self) -> Option<Vec<u8>> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::IDK { data, .. } | tlv::RenditionType::Unknown { data, .. }
+                    if EmbeddedImageFormat::from_magic(&data.0)
+                        == Some(EmbeddedImageFormat::PDF) =>
+                {
+                    Some(data.0)
+                }
+                _ => None,
+            })
+    }
+
+    // link held by InternalReference, LayerReference and ContentRendition
+    // renditions
+    pub fn rendition_link(&self) -> Option<&rendition::RenditionLink> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::InternalLink(rendition_link)) => Some(rendition_link),
+            _ => None,
+        }
+    }
+
+    // links to the layers of a LayerStack rendition, in drawing order
+    pub fn layer_stack_layers(&self) -> Option<&[rendition::RenditionLink]> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::LayerStack { layers }) => Some(layers),
+            _ => None,
+        }
+    }
+
+    // where an InternalReference rendition, e.g. a sub-image of a packed
+    // image, lies in the rendition it references
+    pub fn internal_link_frame(&self) -> Option<SpriteRect> {
+        self.rendition_link()
+            .map(|rendition_link| rendition_link.frame())
+    }
+
+    // PDF of a Vector rendition
+    pub fn pdf_data(&self) -> Option<&[u8]> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::Vector { pdf_data, .. }) => Some(&pdf_data.0),
+            _ => None,
+        }
+    }
+
+    pub fn write_pdf<W: Write>(&self, mut w: W) -> Result<(), CarError> {
+        let pdf_data = self.pdf_data().ok_or(CarError::UnsupportedLayout(
+            self.csimetadata.layout.to_u32() as u16,
+        ))?;
+        w.write_all(pdf_data)?;
+        Ok(())
+    }
+
+    // images entry of an imageset Contents.json, the idiom, scale, appearance
+    // and subtype come from the rendition key like in the assetutil output
+    #[cfg(feature = "serde")]
+    pub fn to_contents_entry(
+        &self,
+        rendition_key: &rendition::Key,
+        key_format: &rendition::KeyFormat,
+        appearancedb: &std::collections::BTreeMap<String, u32>,
+    ) -> super::contents_json::ImageEntry {
+        use super::contents_json;
+
+        let attribute = |attribute| {
+            rendition_key
+                .find_attribute(key_format, attribute)
+                .unwrap_or(0)
+        };
+        let appearance = attribute(rendition::AttributeType::Appearance);
+        let appearances = appearancedb
+            .iter()
+            .find(|(_, appearance_index)| appearance > 0 && **appearance_index == appearance as u32)
+            .and_then(|(appearance_name, _)| contents_json::Appearance::from_name(appearance_name))
+            .into_iter()
+            .collect();
+        // keys without a Scale attribute fall back to the header
+        let scale = match (
+            self.csimetadata.layout,
+            attribute(rendition::AttributeType::Scale),
+            self.scale_factor,
+        ) {
+            (rendition::LayoutType32::Vector, _, _) | (_, 0, 0) => None,
+            (_, 0, scale_factor) => Some(format!("{}x", scale_factor / 100)),
+            (_, scale, _) => Some(format!("{}x", scale)),
+        };
+        contents_json::ImageEntry {
+            appearances,
+            filename: self.csimetadata.name(),
+            idiom: contents_json::idiom_name(attribute(rendition::AttributeType::Idiom))
+                .to_string(),
+            scale,
+            subtype: contents_json::subtype_name(attribute(rendition::AttributeType::Subtype))
+                .map(|subtype| subtype.to_string()),
+        }
+    }
+
+    // payload of a Data rendition, decompressed when stored compressed
+    pub fn write_data<W: Write>(&self, w: &mut W) -> Result<(), CarError> {
+        if self.csimetadata.layout != rendition::LayoutType32::Data {
+            return Err(CarError::UnsupportedLayout(
+                self.csimetadata.layout.to_u32() as u16,
+            ));
+        }
+        let raw_data = match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. })
+            | Some(rendition::Rendition::Theme { raw_data, .. })
+            | Some(rendition::Rendition::ThemeCBCK { raw_data, .. }) => &raw_data.0,
+            _ => {
+                return Err(CarError::UnsupportedLayout(
+                    self.csimetadata.layout.to_u32() as u16,
+                ))
+            }
+        };
+        match self.compression_type() {
+            Some(CompressionType::LZFSE) => {
+                let mut data = vec![];
+                lzfse_rust::decode_bytes(raw_data, &mut data)
+                    .map_err(|err| CarError::Parse(err.to_string()))?;
+                w.write_all(&data)?;
+            }
+            // zlib stream
+            Some(CompressionType::ZIP) => {
+                std::io::copy(&mut flate2::read::ZlibDecoder::new(&raw_data[..]), w)?;
+            }
+            Some(CompressionType::Uncompressed) => w.write_all(raw_data)?,
+            compression_type => {
+                return Err(CarError::Parse(format!(
+                    "unhandled compression type {:?} for data {:?}",
+                    compression_type,
+                    self.csimetadata.name()
+                )))
+            }
+        }
+        Ok(())
+    }
+
     pub fn extract(&self, path: &str) -> Result<Option<String>> {
         let name = self.csimetadata.name();
-        let output_path = Path::new(path).join(&name);
+        let output_path = output_path_for_name(Path::new(path), &name)?;
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         let output_path_str = output_path
             .to_str()
             .context(format!("Unable to get output path for {:?}", name))?;
@@ -190,48 +702,22 @@ impl Header {
                     ..
                 }) => match compression_type {
                     CompressionType::ASTC => {
-                        let mut uncompressed_rendition_data = vec![];
-                        // first 12 bytes are a header??
-                        lzfse_rust::decode_bytes(
-                            &raw_data.0[12..],
-                            &mut uncompressed_rendition_data,
-                        )?;
-                        fs::write(&output_path, &uncompressed_rendition_data)?;
+                        fs::write(&output_path, self.astc_data()?)?;
                         Ok(Some(output_path_str.to_string()))
                     }
-                    CompressionType::PaletteImg => {
-                        let mut uncompressed_rendition_data = vec![];
-                        lzfse_rust::decode_bytes(&raw_data.0, &mut uncompressed_rendition_data)?;
-                        let mut reader = Cursor::new(&mut uncompressed_rendition_data);
-                        let quantized_image = rendition::QuantizedImage::read_args(
-                            &mut reader,
-                            (self.width, self.height),
-                        )?;
-                        let image_size = self.width * self.height * 4;
-                        let mut image_buffer = vec![0u8; image_size as usize];
-                        quantized_image.extract(&mut image_buffer);
-
+                    CompressionType::PaletteImg
+                    | CompressionType::Uncompressed
+                    | CompressionType::ZIP
+                    | CompressionType::LZFSE => {
                         let file = File::create(&output_path)?;
-                        let ref mut w = BufWriter::new(file);
-                        let mut encoder = png::Encoder::new(w, self.width, self.height);
-                        encoder.set_color(png::ColorType::Rgba);
-                        encoder.set_depth(png::BitDepth::Eight);
-                        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
-                        encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
-                        let source_chromaticities = png::SourceChromaticities::new(
-                            (0.31270, 0.32900),
-                            (0.64000, 0.33000),
-                            (0.30000, 0.60000),
-                            (0.15000, 0.06000),
-                        );
-                        encoder.set_source_chromaticities(source_chromaticities);
-                        let mut writer = encoder.write_header()?;
-                        writer.write_image_data(&image_buffer)?;
+                        self.write_png(BufWriter::new(file))?;
                         Ok(Some(output_path_str.to_string()))
                     }
                     CompressionType::HEVC => {
-                        // first 8 bytes are a header??
-                        fs::write(&output_path, &raw_data.0[8..])?;
+                        let heic_data = self
+                            .raw_bytes()
+                            .context(format!("truncated HEVC data for image {:?}", name))?;
+                        fs::write(&output_path, heic_data)?;
                         Ok(Some(output_path_str.to_string()))
                     }
                     _ => None.context(format!(
@@ -244,41 +730,725 @@ impl Header {
                     name, self.csimetadata.layout, &self.rendition_data
                 )),
             },
+            rendition::LayoutType32::Data => match self.embedded_image_format() {
+                Some(format) => {
+                    let mut output_path = output_path.clone();
+                    if output_path.extension().is_none() {
+                        output_path.set_extension(format.extension());
+                    }
+                    let output_path_str = output_path
+                        .to_str()
+                        .context(format!("Unable to get output path for {:?}", name))?;
+                    if let Some(rendition::Rendition::RawData { raw_data, .. }) =
+                        &self.rendition_data
+                    {
+                        fs::write(&output_path, &raw_data.0)?;
+                    }
+                    Ok(Some(output_path_str.to_string()))
+                }
+                None => Ok(None),
+            },
+            rendition::LayoutType32::Vector => {
+                let mut output_path = output_path.clone();
+                if output_path.extension().is_none() {
+                    output_path.set_extension("pdf");
+                }
+                let output_path_str = output_path
+                    .to_str()
+                    .context(format!("Unable to get output path for {:?}", name))?;
+                self.write_pdf(File::create(&output_path)?)?;
+                Ok(Some(output_path_str.to_string()))
+            }
             _ => Ok(None),
         }
     }
 
-    pub fn is_opaque(&self) -> bool {
-        // it seems like this actually has to check if the image has any transparent pixels
+    // decodes the bitmap and encodes it as an RGBA PNG
+    pub fn write_png<W: Write>(&self, w: W) -> Result<(), CarError> {
+        if matches!(
+            self.csimetadata.layout,
+            rendition::LayoutType32::Data
+                | rendition::LayoutType32::Color
+                | rendition::LayoutType32::Vector
+        ) || matches!(
+            self.rendition_data,
+            Some(rendition::Rendition::Color { .. })
+        ) {
+            return Err(CarError::NotAnImage);
+        }
+        let decoded_image = self.decoded_image()?;
+        let mut image_buffer = decoded_image.pixels;
+        // PNG uses straight alpha
+        unpremultiply(&mut image_buffer);
+
+        let mut encoder = png::Encoder::new(w, decoded_image.width, decoded_image.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
+        encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
+        let source_chromaticities = png::SourceChromaticities::new(
+            (0.31270, 0.32900),
+            (0.64000, 0.33000),
+            (0.30000, 0.60000),
+            (0.15000, 0.06000),
+        );
+        encoder.set_source_chromaticities(source_chromaticities);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&image_buffer)?;
+        Ok(())
+    }
+
+    // how the payload is stored, data renditions are never compressed
+    pub fn compression_type(&self) -> Option<CompressionType> {
         match &self.rendition_data {
             Some(rendition::Rendition::Theme {
-                compression_type,
+                compression_type, ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type, ..
+            }) => Some(*compression_type),
+            Some(rendition::Rendition::RawData { .. }) => match self.csimetadata.layout {
+                rendition::LayoutType32::Data => Some(CompressionType::Uncompressed),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // bit depth of each color component, from the pixel format
+    pub fn bits_per_component(&self) -> u32 {
+        match self.pixel_format {
+            PixelFormat::ARGB16 | PixelFormat::Gray16 | PixelFormat::ARGBHalf => 16,
+            _ => 8,
+        }
+    }
+
+    // encoding of the bitmap, HEVC payloads and JPEGs stored with an ARGB
+    // pixel format are told apart by their compression or payload
+    pub fn bitmap_encoding(&self) -> BitmapEncoding {
+        if matches!(self.compression_type(), Some(CompressionType::HEVC)) {
+            return BitmapEncoding::HEIF;
+        }
+        if self.is_jpeg() {
+            return BitmapEncoding::JPEG;
+        }
+        match self.pixel_format {
+            PixelFormat::ARGB => BitmapEncoding::RGB,
+            PixelFormat::Gray | PixelFormat::Gray16 => BitmapEncoding::Gray,
+            PixelFormat::ARGB16 | PixelFormat::ARGBHalf => BitmapEncoding::ARGB16,
+            PixelFormat::JPEG => BitmapEncoding::JPEG,
+            PixelFormat::None | PixelFormat::Data => BitmapEncoding::None,
+        }
+    }
+
+    // the pixel format, the compression type or the payload itself can say
+    // the bitmap is a JPEG, any of them is enough
+    pub fn is_jpeg(&self) -> bool {
+        if matches!(self.pixel_format, PixelFormat::JPEG) {
+            return true;
+        }
+        match &self.rendition_data {
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::JPEGLZFSE,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type: CompressionType::JPEGLZFSE,
+                ..
+            }) => true,
+            Some(rendition::Rendition::RawData { raw_data, .. }) => matches!(
+                EmbeddedImageFormat::from_magic(&raw_data.0),
+                Some(EmbeddedImageFormat::JPEG)
+            ),
+            _ => false,
+        }
+    }
+
+    // decodes the bitmap to RGBA, JPEGs carry their own size which may not
+    // match the header
+    pub fn decoded_image(&self) -> Result<DecodedImage> {
+        let mut pixels = vec![];
+        let bitmap_layout = self.decode_into(&mut pixels)?;
+        Ok(DecodedImage {
+            width: bitmap_layout.width,
+            height: bitmap_layout.height,
+            pixels,
+        })
+    }
+
+    // alpha channel of the bitmap, opaque images give an all 255 mask
+    pub fn alpha_mask(&self) -> Result<GrayImage> {
+        let decoded_image = self.decoded_image()?;
+        Ok(GrayImage {
+            width: decoded_image.width,
+            height: decoded_image.height,
+            pixels: decoded_image
+                .pixels
+                .chunks_exact(4)
+                .map(|pixel| pixel[3])
+                .collect(),
+        })
+    }
+
+    fn decode_jpeg(&self) -> Result<DecodedImage> {
+        let jpeg_data = match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. }) => &raw_data.0,
+            _ => {
+                return None.context(format!(
+                    "unsupported JPEG rendition for {:?}, rendition={:?}",
+                    self.csimetadata.name(),
+                    &self.rendition_data
+                ))
+            }
+        };
+        let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(jpeg_data));
+        let pixels = decoder.decode()?;
+        let info = decoder.info().context(format!(
+            "missing JPEG header for {:?}",
+            self.csimetadata.name()
+        ))?;
+        let pixels = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => pixels
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xff])
+                .collect(),
+            jpeg_decoder::PixelFormat::L8 => pixels
+                .iter()
+                .flat_map(|&gray| [gray, gray, gray, 0xff])
+                .collect(),
+            pixel_format => {
+                return None.context(format!(
+                    "unsupported JPEG pixel format {:?} for {:?}",
+                    pixel_format,
+                    self.csimetadata.name()
+                ))
+            }
+        };
+        Ok(DecodedImage {
+            width: info.width as u32,
+            height: info.height as u32,
+            pixels,
+        })
+    }
+
+    // decodes the bitmap to RGBA
+    pub fn decoded_pixels(&self) -> Result<Vec<u8>> {
+        let mut pixels = vec![];
+        self.decode_into(&mut pixels)?;
+        Ok(pixels)
+    }
+
+    // decodes the bitmap to RGBA into buffer, replacing its contents, so
+    // decoding many renditions can reuse one allocation
+    pub fn decode_into(&self, buffer: &mut Vec<u8>) -> Result<BitmapLayout> {
+        buffer.clear();
+        if self.is_jpeg() {
+            let decoded_image = self.decode_jpeg()?;
+            buffer.extend_from_slice(&decoded_image.pixels);
+            return Ok(BitmapLayout::rgba(
+                decoded_image.width,
+                decoded_image.height,
+            ));
+        }
+        match &self.rendition_data {
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::PaletteImg,
+                raw_data,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type: CompressionType::PaletteImg,
+                raw_data,
+                ..
+            }) => {
+                let mut uncompressed_rendition_data = vec![];
+                lzfse_rust::decode_bytes(&raw_data.0, &mut uncompressed_rendition_data)?;
+                // each pixel is a one byte palette index
+                let pixel_count = self.checked_pixel_count(1, uncompressed_rendition_data.len())?;
+                let mut reader = Cursor::new(&mut uncompressed_rendition_data);
+                let quantized_image =
+                    rendition::QuantizedImage::read_args(&mut reader, (self.width, self.height))?;
+                buffer.resize(pixel_count as usize * 4, 0);
+                quantized_image.extract(buffer);
+            }
+            Some(rendition::Rendition::Theme {
+                compression_type:
+                    compression_type @ (CompressionType::Uncompressed
+                    | CompressionType::ZIP
+                    | CompressionType::LZFSE),
+                raw_data,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type:
+                    compression_type @ (CompressionType::Uncompressed
+                    | CompressionType::ZIP
+                    | CompressionType::LZFSE),
                 raw_data,
                 ..
             }) => {
                 match compression_type {
-                    CompressionType::PaletteImg => {
-                        let mut uncompressed_rendition_data = vec![];
-                        lzfse_rust::decode_bytes(&raw_data.0, &mut uncompressed_rendition_data)
-                            .unwrap();
-                        let mut reader = Cursor::new(&mut uncompressed_rendition_data);
-                        let quantized_image = rendition::QuantizedImage::read_args(
-                            &mut reader,
-                            (self.width, self.height),
-                        )
-                        .unwrap();
-                        // any non 0xff values for the alpha channel?
-                        !quantized_image
-                            .color_table
-                            .iter()
-                            .any(|pixel| (*pixel & 0xff) != 0xff)
+                    CompressionType::LZFSE => {
+                        lzfse_rust::decode_bytes(&raw_data.0, buffer)?;
+                    }
+                    // zlib stream
+                    CompressionType::ZIP => {
+                        flate2::read::ZlibDecoder::new(&raw_data.0[..]).read_to_end(buffer)?;
+                    }
+                    _ => buffer.extend_from_slice(&raw_data.0),
+                };
+                // 16 bit components are narrowed to 8 bits first, half floats
+                // are clamped to 0...1 (extended range values are lost)
+                match self.pixel_format {
+                    PixelFormat::ARGB16 | PixelFormat::Gray16 => {
+                        narrow_components(buffer, |component| {
+                            ((component as u32 * 255 + 32767) / 65535) as u8
+                        })
+                    }
+                    PixelFormat::ARGBHalf => narrow_components(buffer, |component| {
+                        (half_to_f32(component).clamp(0.0, 1.0) * 255.0).round() as u8
+                    }),
+                    _ => {}
+                }
+                if matches!(self.pixel_format, PixelFormat::Gray | PixelFormat::Gray16) {
+                    // gray and alpha rows without padding, expanded in place
+                    // from the end so no pixel is overwritten before it's read
+                    let pixel_count = self.checked_pixel_count(2, buffer.len())? as usize;
+                    buffer.truncate(pixel_count * 2);
+                    buffer.resize(pixel_count * 4, 0);
+                    for i in (0..pixel_count).rev() {
+                        let (gray, alpha) = (buffer[i * 2], buffer[i * 2 + 1]);
+                        buffer[i * 4..i * 4 + 4].copy_from_slice(&[gray, gray, gray, alpha]);
+                    }
+                } else {
+                    // BGRA rows without padding
+                    let pixel_count = self.checked_pixel_count(4, buffer.len())?;
+                    buffer.truncate(pixel_count as usize * 4);
+                    for pixel in buffer.chunks_exact_mut(4) {
+                        pixel.swap(0, 2);
                     }
-                    _ => self.rendition_flags.is_opaque(),
                 }
             }
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::ASTC,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type: CompressionType::ASTC,
+                ..
+            }) => {
+                return None.context(format!(
+                    "unsupported GPU texture format ASTC for {:?}",
+                    self.csimetadata.name()
+                ))
+            }
+            _ => {
+                return None.context(format!(
+                    "unable to decode pixels for {:?}, rendition={:?}",
+                    self.csimetadata.name(),
+                    &self.rendition_data
+                ))
+            }
+        }
+        Ok(BitmapLayout::rgba(self.width, self.height))
+    }
+
+    // ASTC blocks of a GPU texture, LZFSE compressed after a 12 byte header
+    pub fn astc_data(&self) -> Result<Vec<u8>> {
+        let raw_data = match &self.rendition_data {
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::ASTC,
+                raw_data,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type: CompressionType::ASTC,
+                raw_data,
+                ..
+            }) => raw_data,
+            _ => {
+                return None.context(format!(
+                    "no ASTC data for {:?}, rendition={:?}",
+                    self.csimetadata.name(),
+                    &self.rendition_data
+                ))
+            }
+        };
+        let compressed = raw_data.0.get(12..).context(format!(
+            "truncated ASTC data for {:?}",
+            self.csimetadata.name()
+        ))?;
+        let mut astc_data = vec![];
+        lzfse_rust::decode_bytes(compressed, &mut astc_data)?;
+        Ok(astc_data)
+    }
+
+    // number of pixels, after checking they fit in the decoded bytes
+    fn checked_pixel_count(
+        &self,
+        bytes_per_pixel: u64,
+        decoded_length: usize,
+    ) -> Result<u64, CarError> {
+        let pixel_count = self.width as u64 * self.height as u64;
+        if pixel_count * bytes_per_pixel > decoded_length as u64 {
+            return Err(CarError::SizeMismatch {
+                expected: pixel_count * bytes_per_pixel,
+                actual: decoded_length as u64,
+            });
+        }
+        Ok(pixel_count)
+    }
+
+    // decodes the bitmap and converts it to the layout requested in options,
+    // decoded pixels are already premultiplied
+    pub fn decoded_pixels_with_options(&self, options: &DecodeOptions) -> Result<Vec<u8>> {
+        let mut pixels = self.decoded_pixels()?;
+        if !options.premultiply_alpha {
+            unpremultiply(&mut pixels);
+        }
+        for pixel in pixels.chunks_exact_mut(4) {
+            match options.channel_order {
+                ChannelOrder::RGBA => {}
+                ChannelOrder::BGRA => pixel.swap(0, 2),
+                ChannelOrder::ARGB => pixel.rotate_right(1),
+            }
+        }
+        Ok(pixels)
+    }
+
+    // SHA256 of the decoded RGBA pixels, unlike the stored digest this doesn't
+    // depend on how the bitmap is compressed
+    pub fn pixel_hash(&self) -> Result<[u8; 32]> {
+        let pixels = self.decoded_pixels()?;
+        let mut hasher = Sha256::new();
+        hasher.update(self.width.to_le_bytes());
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(&pixels);
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        Ok(digest)
+    }
+
+    // estimates the payload size if the decoded bitmap was compressed with compression_type,
+    // the catalog itself is not modified
+    pub fn recompress(&self, compression_type: CompressionType) -> Result<RecompressResult> {
+        let size = self.recompressed_payload(compression_type)?.len();
+        Ok(RecompressResult {
+            compression_type,
+            size,
+            size_on_disk: self.size_on_disk(),
+        })
+    }
+
+    // the decoded bitmap as BGRA rows compressed with compression_type, the
+    // payload of an ARGB rendition
+    pub fn recompressed_payload(&self, compression_type: CompressionType) -> Result<Vec<u8>> {
+        let mut pixels = self.decoded_pixels()?;
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        match compression_type {
+            CompressionType::Uncompressed => Ok(pixels),
+            CompressionType::LZFSE => {
+                let mut compressed = vec![];
+                lzfse_rust::encode_bytes(&pixels, &mut compressed)?;
+                Ok(compressed)
+            }
+            // zlib stream, like the decoder expects
+            CompressionType::ZIP => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(&pixels)?;
+                Ok(encoder.finish()?)
+            }
+            _ => None.context(format!(
+                "unsupported compression type \"{:?}\" for recompression",
+                compression_type
+            )),
+        }
+    }
+
+    // size on disk relative to the size of the decoded RGBA bitmap
+    pub fn compression_ratio(&self) -> Option<f32> {
+        let decoded_size = self.pixel_width()? as u64 * self.pixel_height()? as u64 * 4;
+        if decoded_size == 0 {
+            return None;
+        }
+        Some(self.size_on_disk() as f32 / decoded_size as f32)
+    }
+
+    // SHA256 of the header serialized again: the 184 byte CSI header, the TLVs
+    // and the rendition data, payloads that aren't kept when parsing (e.g.
+    // external links) are missing, see CommonAssetStorage::verify_digest
+    pub fn sha256_digest(&self) -> Result<Vec<u8>, CarError> {
+        let mut writer = Cursor::new(vec![]);
+        self.write_options(&mut writer, self.endian, ())?;
+        let mut hasher = Sha256::new();
+        hasher.update(writer.into_inner());
+        Ok(hasher.finalize().to_vec())
+    }
+
+    pub fn size_on_disk(&self) -> u32 {
+        // 184 is the size of the csi header struct
+        184 + self.csibitmaplist.tlv_length + self.csibitmaplist.rendition_length
+    }
+
+    // payload of the rendition, for HEVC this is the HEIC stream
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. }) => Some(&raw_data.0),
+            Some(rendition::Rendition::Vector { pdf_data, .. }) => Some(&pdf_data.0),
+            Some(rendition::Rendition::Theme {
+                compression_type,
+                raw_data,
+                ..
+            })
+            | Some(rendition::Rendition::ThemeCBCK {
+                compression_type,
+                raw_data,
+                ..
+            }) => match compression_type {
+                // first 8 bytes are a header??
+                CompressionType::HEVC => raw_data.0.get(8..),
+                _ => Some(&raw_data.0),
+            },
+            _ => None,
+        }
+    }
+
+    // decodes an HEVC compressed image to RGBA, returns (width, height, pixels)
+    #[cfg(feature = "heic")]
+    pub fn decode_heic(&self) -> Result<(u32, u32, Vec<u8>)> {
+        use libheif_rs::ColorSpace;
+        use libheif_rs::HeifContext;
+        use libheif_rs::LibHeif;
+        use libheif_rs::RgbChroma;
+
+        let heic_data = self.raw_bytes().context("rendition has no HEVC data")?;
+        let context = HeifContext::read_from_bytes(heic_data)?;
+        let handle = context.primary_image_handle()?;
+        let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+        let plane = image
+            .planes()
+            .interleaved
+            .context("decoded HEIC image has no interleaved plane")?;
+
+        let row_length = plane.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_length * plane.height as usize);
+        for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+            pixels.extend_from_slice(&row[..row_length]);
+        }
+        Ok((plane.width, plane.height, pixels))
+    }
+
+    // sniffs the payload of Data renditions for a known image format
+    pub fn embedded_image_format(&self) -> Option<EmbeddedImageFormat> {
+        if !matches!(self.pixel_format, PixelFormat::Data) {
+            return None;
+        }
+        match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. }) => {
+                EmbeddedImageFormat::from_magic(&raw_data.0)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        // an opacity recorded in the TLV below 1 makes the whole image translucent
+        if let Some(opacity) = self.opacity() {
+            if opacity < 1.0 {
+                return false;
+            }
+        }
+        // it seems like this actually has to check if the image has any transparent pixels
+        match &self.rendition_data {
+            Some(rendition::Rendition::Theme {
+                compression_type: CompressionType::PaletteImg,
+                raw_data,
+                ..
+            }) => match self.palette_is_opaque(&raw_data.0) {
+                Some(is_opaque) => is_opaque,
+                None => self.rendition_flags.is_opaque(),
+            },
             _ => self.rendition_flags.is_opaque(),
         }
     }
+
+    // None when the palette can't be decoded
+    fn palette_is_opaque(&self, raw_data: &[u8]) -> Option<bool> {
+        let mut uncompressed_rendition_data = vec![];
+        lzfse_rust::decode_bytes(raw_data, &mut uncompressed_rendition_data).ok()?;
+        let mut reader = Cursor::new(&mut uncompressed_rendition_data);
+        let quantized_image =
+            rendition::QuantizedImage::read_args(&mut reader, (self.width, self.height)).ok()?;
+        // any non 0xff values for the alpha channel?
+        Some(
+            !quantized_image
+                .color_table
+                .iter()
+                .any(|pixel| (*pixel & 0xff) != 0xff),
+        )
+    }
+}
+
+// replaces the little endian 16 bit components of buffer by one byte each
+fn narrow_components(buffer: &mut Vec<u8>, narrow: impl Fn(u16) -> u8) {
+    let component_count = buffer.len() / 2;
+    for i in 0..component_count {
+        buffer[i] = narrow(u16::from_le_bytes([buffer[i * 2], buffer[i * 2 + 1]]));
+    }
+    buffer.truncate(component_count);
+}
+
+// IEEE 754 half precision float
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// bitmaps are stored with premultiplied alpha, scales the components of RGBA
+// pixels back up to straight alpha
+fn unpremultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha == 0 || alpha == 255 {
+            continue;
+        }
+        for component in &mut pixel[..3] {
+            *component = ((*component as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ChannelOrder {
+    #[default]
+    RGBA,
+    BGRA,
+    ARGB,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    pub channel_order: ChannelOrder,
+    pub premultiply_alpha: bool,
+}
+
+#[derive(Debug)]
+pub struct RecompressResult {
+    pub compression_type: CompressionType,
+    pub size: usize,
+    pub size_on_disk: u32,
+}
+
+// size of a bitmap decoded to RGBA rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitmapLayout {
+    pub width: u32,
+    pub height: u32,
+    pub bytes_per_row: u32,
+}
+
+impl BitmapLayout {
+    fn rgba(width: u32, height: u32) -> BitmapLayout {
+        BitmapLayout {
+            width,
+            height,
+            bytes_per_row: width * 4,
+        }
+    }
+}
+
+// RGBA bitmap
+#[derive(Debug)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl DecodedImage {
+    // crops a sub-image of a packed image, packed images sharing one alpha
+    // plane between their sub-images pass it as shared_alpha
+    pub fn sub_image(
+        &self,
+        rect: &SpriteRect,
+        shared_alpha: Option<&GrayImage>,
+    ) -> Result<DecodedImage> {
+        let fits = |start: u32, length: u32, limit: u32| {
+            start.checked_add(length).is_some_and(|end| end <= limit)
+        };
+        if !fits(rect.x, rect.width, self.width) || !fits(rect.y, rect.height, self.height) {
+            return None.context(format!(
+                "sub-image {:?} is outside of the {}x{} image",
+                rect, self.width, self.height
+            ));
+        }
+        if let Some(shared_alpha) = shared_alpha {
+            if (shared_alpha.width, shared_alpha.height) != (self.width, self.height) {
+                return None.context(format!(
+                    "shared alpha is {}x{} for a {}x{} image",
+                    shared_alpha.width, shared_alpha.height, self.width, self.height
+                ));
+            }
+        }
+
+        // u64 math, the sizes come from the catalog
+        let pixel_count = self.width as u64 * self.height as u64;
+        if (self.pixels.len() as u64) < pixel_count * 4
+            || shared_alpha
+                .is_some_and(|shared_alpha| (shared_alpha.pixels.len() as u64) < pixel_count)
+        {
+            return None.context(format!(
+                "{}x{} image is missing pixels",
+                self.width, self.height
+            ));
+        }
+
+        let mut pixels = Vec::with_capacity((rect.width as u64 * rect.height as u64 * 4) as usize);
+        for y in rect.y..rect.y + rect.height {
+            let offset = (y as u64 * self.width as u64 + rect.x as u64) as usize;
+            let row = &self.pixels[offset * 4..(offset + rect.width as usize) * 4];
+            match shared_alpha {
+                Some(shared_alpha) => {
+                    let alpha_row = &shared_alpha.pixels[offset..offset + rect.width as usize];
+                    for (pixel, alpha) in row.chunks_exact(4).zip(alpha_row) {
+                        pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], *alpha]);
+                    }
+                }
+                None => pixels.extend_from_slice(row),
+            }
+        }
+        Ok(DecodedImage {
+            width: rect.width,
+            height: rect.height,
+            pixels,
+        })
+    }
+}
+
+// one byte per pixel
+#[derive(Debug)]
+pub struct GrayImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// ARKit reference objects, the type comes from the UTI TLV
+#[derive(Debug)]
+pub struct RecognitionObject<'a> {
+    pub name: String,
+    pub object_type: Option<String>,
+    pub data: &'a [u8],
 }
 
 #[derive(Debug, Default)]
@@ -411,3 +1581,16 @@ impl Generator {
 pub trait CSIRepresentation {
     // TODO: fill out
 }
+
+// names like "Folder/Asset" are recreated as nested directories, but must
+// stay inside the output directory
+pub(crate) fn output_path_for_name(path: &Path, name: &str) -> Result<PathBuf> {
+    let relative_path = Path::new(name);
+    if relative_path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None.context(format!("refusing to extract to unsafe path {:?}", name));
+    }
+    Ok(path.join(relative_path))
+}
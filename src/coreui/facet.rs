@@ -0,0 +1,141 @@
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use super::csi;
+use super::rendition;
+use super::CommonAssetStorage;
+
+// renditions sharing a facet name, e.g. all scales and idioms of an image
+pub struct Facet<'a> {
+    pub name: &'a str,
+    pub renditions: Vec<(&'a rendition::Key, &'a csi::Header)>,
+    pub asset_storage: &'a CommonAssetStorage,
+}
+
+// one mip level of a texture, ASTC levels are GPU blocks that can't be decoded
+#[derive(Debug)]
+pub enum MipLevel {
+    Decoded(csi::DecodedImage),
+    ASTC {
+        width: u32,
+        height: u32,
+        blocks: Vec<u8>,
+    },
+}
+
+impl MipLevel {
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            MipLevel::Decoded(decoded_image) => (decoded_image.width, decoded_image.height),
+            MipLevel::ASTC { width, height, .. } => (*width, *height),
+        }
+    }
+}
+
+impl Facet<'_> {
+    // distinct values of attribute across the renditions of this facet
+    fn attribute_values(&self, attribute: rendition::AttributeType) -> BTreeSet<u16> {
+        self.renditions
+            .iter()
+            .filter_map(|(rendition_key, _)| {
+                rendition_key.find_attribute(&self.asset_storage.renditionkeyfmt, attribute)
+            })
+            .collect()
+    }
+
+    pub fn available_scales(&self) -> Vec<u32> {
+        self.attribute_values(rendition::AttributeType::Scale)
+            .into_iter()
+            .map(|scale| scale as u32)
+            .collect()
+    }
+
+    // names of the appearances with renditions, e.g. to find facets without a
+    // dark variant
+    pub fn appearances(&self) -> Vec<String> {
+        self.renditions
+            .iter()
+            .map(|(rendition_key, _)| {
+                rendition_key
+                    .find_attribute(
+                        &self.asset_storage.renditionkeyfmt,
+                        rendition::AttributeType::Appearance,
+                    )
+                    .unwrap_or(0)
+            })
+            .collect::<BTreeSet<u16>>()
+            .into_iter()
+            .map(|appearance| self.asset_storage.appearance_name(appearance))
+            .collect()
+    }
+
+    // color components (red, green, blue, alpha) keyed by appearance name
+    pub fn colors_by_appearance(&self) -> HashMap<String, [f64; 4]> {
+        self.renditions
+            .iter()
+            .filter_map(|(rendition_key, csi_header)| {
+                let components = match &csi_header.rendition_data {
+                    Some(rendition::Rendition::Color { components, .. }) => components,
+                    _ => return None,
+                };
+                let rgba = match components.as_slice() {
+                    [red, green, blue, alpha] => [*red, *green, *blue, *alpha],
+                    [gray, alpha] => [*gray, *gray, *gray, *alpha],
+                    _ => return None,
+                };
+                let appearance = rendition_key
+                    .find_attribute(
+                        &self.asset_storage.renditionkeyfmt,
+                        rendition::AttributeType::Appearance,
+                    )
+                    .unwrap_or(0);
+                Some((self.asset_storage.appearance_name(appearance), rgba))
+            })
+            .collect()
+    }
+
+    // TextureImage renditions of a texture in mip order, the level is the
+    // Dimension1 attribute of the key and 0 is the full size image
+    pub fn mip_levels(&self) -> Result<Vec<MipLevel>> {
+        let mut mip_levels: Vec<(u16, &csi::Header)> = self
+            .renditions
+            .iter()
+            .filter(|(_, csi_header)| {
+                csi_header.csimetadata.layout == rendition::LayoutType32::TextureImage
+            })
+            .map(|(rendition_key, csi_header)| {
+                let level = rendition_key
+                    .find_attribute(
+                        &self.asset_storage.renditionkeyfmt,
+                        rendition::AttributeType::Dimension1,
+                    )
+                    .unwrap_or(0);
+                (level, *csi_header)
+            })
+            .collect();
+        mip_levels.sort_by_key(|(level, _)| *level);
+        mip_levels
+            .into_iter()
+            .map(|(_, csi_header)| match &csi_header.rendition_data {
+                Some(rendition::Rendition::Theme {
+                    compression_type: rendition::CompressionType::ASTC,
+                    ..
+                })
+                | Some(rendition::Rendition::ThemeCBCK {
+                    compression_type: rendition::CompressionType::ASTC,
+                    ..
+                }) => Ok(MipLevel::ASTC {
+                    width: csi_header.width,
+                    height: csi_header.height,
+                    blocks: csi_header.astc_data()?,
+                }),
+                _ => Ok(MipLevel::Decoded(csi::DecodedImage {
+                    width: csi_header.width,
+                    height: csi_header.height,
+                    pixels: csi_header.decoded_pixels()?,
+                })),
+            })
+            .collect()
+    }
+}
@@ -1,9 +1,19 @@
 pub mod bitmap;
 mod car_util_asset_storage;
 mod color;
+#[cfg(feature = "serde")]
+pub mod contents_json;
 pub mod csi;
+mod facet;
 pub mod rendition;
+mod sprite_sheet;
 pub mod tlv;
+#[cfg(feature = "serde")]
+mod xcassets;
 
 pub use self::car_util_asset_storage::*;
 pub use self::color::*;
+pub use self::facet::*;
+pub use self::sprite_sheet::*;
+#[cfg(feature = "serde")]
+pub use self::xcassets::*;
@@ -1,25 +1,72 @@
 use binrw::BinRead;
+use binrw::BinResult;
 use binrw::BinWrite;
+use binrw::Endian;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+#[cfg(feature = "serde")]
 use serde::Serialize;
+#[cfg(feature = "serde")]
 use serde::Serializer;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::iter::zip;
 
 use crate::common::RawData;
 use crate::coregraphics;
+use crate::error::CarError;
 
+use super::SpriteRect;
+
+// the layout is the same for every version seen so far, newer versions only
+// add attribute types (e.g. GlyphWeight and GlyphSize for symbols)
 #[derive(Debug, BinRead, BinWrite)]
-#[brw(little, magic = b"tmfk")]
+// the magic is 'kfmt' as a u32, "tmfk" in little endian catalogs
+#[brw(magic = 0x6B666D74u32)]
 pub struct KeyFormat {
     pub version: u32,
     pub max_count: u32,
-    #[br(count = max_count)]
+    #[br(parse_with = parse_attribute_types, args_raw = max_count)]
     pub attribute_types: Vec<AttributeType>,
 }
 
+// an inflated max_count would read whatever follows the key format, so it's
+// checked against the bytes left and the attributes a key can hold first
+fn parse_attribute_types<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    max_count: u32,
+) -> BinResult<Vec<AttributeType>> {
+    let pos = reader.stream_position()?;
+    let available = (reader.seek(SeekFrom::End(0))? - pos) / 4;
+    reader.seek(SeekFrom::Start(pos))?;
+    if max_count as u64 > available.min(KEY_ATTRIBUTE_COUNT as u64) {
+        return Err(binrw::Error::Custom {
+            pos,
+            err: Box::new(CarError::KeyFormatMismatch {
+                max_count,
+                available: available.min(KEY_ATTRIBUTE_COUNT as u64) as u32,
+            }),
+        });
+    }
+
+    (0..max_count)
+        .map(|_| {
+            let pos = reader.stream_position()?;
+            let value = u32::read_options(reader, endian, ())?;
+            AttributeType::from_u32(value).ok_or_else(|| binrw::Error::Custom {
+                pos,
+                err: Box::new(CarError::UnknownAttributeType(value as u16)),
+            })
+        })
+        .collect()
+}
+
 impl KeyFormat {
     pub fn new(attribute_types: Vec<AttributeType>) -> Self {
         KeyFormat {
@@ -32,14 +79,51 @@ impl KeyFormat {
     pub fn map(&self, key: &Key) -> Vec<(AttributeType, u16)> {
         zip(self.attribute_types.clone(), key.raw).collect()
     }
+
+    pub fn attributes(&self) -> &[AttributeType] {
+        &self.attribute_types
+    }
+
+    // index of attribute in rendition keys using this format
+    pub fn position(&self, attribute: AttributeType) -> Option<usize> {
+        self.attribute_types
+            .iter()
+            .position(|attribute_type| *attribute_type == attribute)
+    }
+
+    // builds a key from raw values ordered like this format, missing values are 0
+    pub fn decode(&self, raw_key: &[u16]) -> Key {
+        let mut raw = [0u16; KEY_ATTRIBUTE_COUNT];
+        for (value, raw_value) in raw.iter_mut().zip(raw_key) {
+            *value = *raw_value;
+        }
+        Key { raw }
+    }
+
+    // builds the rendition key named by a key token, attributes missing from
+    // this format are dropped and the ones missing from the token are 0
+    pub fn key_for_token(&self, key_token: &KeyToken) -> Key {
+        let mut raw = [0u16; KEY_ATTRIBUTE_COUNT];
+        for attribute in &key_token.attributes {
+            let position = AttributeType::from_u16(attribute.name as u16)
+                .and_then(|attribute_type| self.position(attribute_type))
+                .filter(|position| *position < KEY_ATTRIBUTE_COUNT);
+            if let Some(position) = position {
+                raw[position] = attribute.value;
+            }
+        }
+        Key { raw }
+    }
 }
 
-#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
-#[brw(little)]
+#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct Key {
-    pub raw: [u16; 18],
+    pub raw: [u16; KEY_ATTRIBUTE_COUNT],
 }
 
+// attributes a rendition key has room for
+pub const KEY_ATTRIBUTE_COUNT: usize = 18;
+
 impl Debug for Key {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("RenditionKey {{ {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {} }}", 
@@ -66,17 +150,22 @@ impl Debug for Key {
 }
 
 impl Key {
-    pub fn find_attribute(&self, key_format: KeyFormat, attribute: AttributeType) -> Option<u16> {
+    pub fn find_attribute(&self, key_format: &KeyFormat, attribute: AttributeType) -> Option<u16> {
         key_format
             .map(self)
             .iter()
             .find(|(attribute_type, _)| *attribute_type == attribute)
             .and_then(|(_, value)| Some(*value))
     }
+
+    // values in key_format order, without the unused trailing slots, the
+    // inverse of KeyFormat::decode
+    pub fn raw_key(&self, key_format: &KeyFormat) -> &[u16] {
+        &self.raw[..key_format.attributes().len().min(KEY_ATTRIBUTE_COUNT)]
+    }
 }
 
-#[derive(BinRead, BinWrite)]
-#[brw(little)]
+#[derive(BinRead, BinWrite, Clone)]
 pub struct KeyToken {
     _cursor_hotspot: (u16, u16),
     _number_of_attributes: u16,
@@ -93,8 +182,49 @@ impl Debug for KeyToken {
     }
 }
 
-#[derive(BinRead, BinWrite, Debug)]
+impl KeyToken {
+    // (x, y) of cursor assets, (0, 0) for everything else
+    pub fn cursor_hotspot(&self) -> (u16, u16) {
+        self._cursor_hotspot
+    }
+
+    // (name, value) pairs sorted by name, stored order of attributes doesn't matter
+    pub fn normalized_attributes(&self) -> Vec<(u16, u16)> {
+        let mut attributes: Vec<(u16, u16)> = self
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.name as u16, attribute.value))
+            .collect();
+        attributes.sort();
+        attributes
+    }
+}
+
+impl PartialEq for KeyToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized_attributes() == other.normalized_attributes()
+    }
+}
+
+impl Eq for KeyToken {}
+
+impl PartialOrd for KeyToken {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.normalized_attributes()
+            .partial_cmp(&other.normalized_attributes())
+    }
+}
+
+impl Hash for KeyToken {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized_attributes().hash(state);
+    }
+}
+
+#[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct Attribute {
+    #[br(try_map = |raw: u16| AttributeType16::from_u16(raw)
+        .ok_or(CarError::UnknownAttributeType(raw)))]
     pub name: AttributeType16,
     pub value: u16,
 }
@@ -128,6 +258,8 @@ pub enum AttributeType16 {
     GraphicsClass,
     DisplayGamut,
     DeploymentTarget,
+    GlyphWeight,
+    GlyphSize,
 }
 
 #[derive(Debug, BinRead, BinWrite, PartialEq, FromPrimitive, Clone, Copy)]
@@ -159,8 +291,47 @@ pub enum AttributeType {
     GraphicsClass,
     DisplayGamut,
     DeploymentTarget,
+    GlyphWeight,
+    GlyphSize,
+}
+
+impl AttributeType {
+    // plain lowercase name, without the kCRTheme...Name decoration
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            AttributeType::Look => "look",
+            AttributeType::Element => "element",
+            AttributeType::Part => "part",
+            AttributeType::Size => "size",
+            AttributeType::Direction => "direction",
+            AttributeType::PlaceHolder => "placeholder",
+            AttributeType::Value => "value",
+            AttributeType::Appearance => "appearance",
+            AttributeType::Dimension1 => "dimension1",
+            AttributeType::Dimension2 => "dimension2",
+            AttributeType::State => "state",
+            AttributeType::Layer => "layer",
+            AttributeType::Scale => "scale",
+            AttributeType::Unknown13 => "unknown13",
+            AttributeType::PresentationState => "presentation_state",
+            AttributeType::Idiom => "idiom",
+            AttributeType::Subtype => "subtype",
+            AttributeType::Identifier => "identifier",
+            AttributeType::PreviousValue => "previous_value",
+            AttributeType::PreviousState => "previous_state",
+            AttributeType::SizeClassHorizontal => "size_class_horizontal",
+            AttributeType::SizeClassVertical => "size_class_vertical",
+            AttributeType::MemoryClass => "memory_class",
+            AttributeType::GraphicsClass => "graphics_class",
+            AttributeType::DisplayGamut => "display_gamut",
+            AttributeType::DeploymentTarget => "deployment_target",
+            AttributeType::GlyphWeight => "glyph_weight",
+            AttributeType::GlyphSize => "glyph_size",
+        }
+    }
 }
 
+#[cfg(feature = "serde")]
 impl Serialize for AttributeType {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -183,16 +354,17 @@ impl Display for AttributeType {
 pub struct ColorFlags(pub u32);
 
 impl ColorFlags {
+    // unknown ids fall back to sRGB
     pub fn color_space(&self) -> coregraphics::ColorSpace {
         let value = self.0 & 0xff; // last byte?
-                                   // coregraphics::ColorSpace::SRGB
-        FromPrimitive::from_u32(value).unwrap()
+        coregraphics::ColorSpace::from_id(value).unwrap_or(coregraphics::ColorSpace::SRGB)
     }
 }
 
+// tags are u32s, e.g. 'COLR' reads as "RLOC" in little endian catalogs
 #[derive(Debug, BinRead, BinWrite, Clone, PartialEq, PartialOrd)]
 pub enum Rendition {
-    #[brw(magic = b"RLOC")]
+    #[brw(magic = 0x434F4C52u32)]
     Color {
         version: u32,
         flags: ColorFlags,
@@ -200,20 +372,29 @@ pub enum Rendition {
         #[br(count = component_count)]
         components: Vec<f64>,
     },
-    #[brw(magic = b"DWAR")]
+    #[brw(magic = 0x52415744u32)]
     RawData {
         version: u32,
         _raw_data_length: u32,
         #[br(count = _raw_data_length)]
         raw_data: RawData,
     },
+    // RawData of a Vector layout, never matched on its own, see
+    // csi::Header's parser
+    #[brw(magic = 0x52415744u32)]
+    Vector {
+        version: u32,
+        _raw_data_length: u32,
+        #[br(count = _raw_data_length)]
+        pdf_data: RawData,
+    },
     // Why is there sometimes two levels here?
-    #[brw(magic = b"MLEC")]
+    #[brw(magic = 0x43454C4Du32)]
     ThemeCBCK {
         version: u32,
         compression_type: CompressionType,
         idk: u32,
-        #[brw(magic = b"KCBC")]
+        #[brw(magic = 0x4342434Bu32)]
         a: u32,
         b: u32,
         c: u32,
@@ -222,7 +403,7 @@ pub enum Rendition {
         raw_data: RawData,
     },
     // CELM ???
-    #[brw(magic = b"MLEC")]
+    #[brw(magic = 0x43454C4Du32)]
     Theme {
         version: u32,
         compression_type: CompressionType,
@@ -230,13 +411,23 @@ pub enum Rendition {
         #[br(count = _raw_data_length)]
         raw_data: RawData,
     },
-    #[brw(magic = b"SISM")]
+    #[brw(magic = 0x4D534953u32)]
     MultisizeImageSet {
         version: u32,
         sizes_count: u32,
         #[br(count = sizes_count)]
         entries: Vec<MultisizeImageSetEntry>,
     },
+    // payload of an InternalReference layout, e.g. a sub-image of a packed
+    // image, or of a LayerReference layout
+    InternalLink(RenditionLink),
+    // payload of a LayerStack layout, an INLK link to each layer in drawing
+    // order. never matched on its own, see csi::Header's parser
+    #[br(pre_assert(false))]
+    LayerStack {
+        #[br(parse_with = binrw::helpers::until_eof)]
+        layers: Vec<RenditionLink>,
+    },
     Unknown {
         tag: u32,
         version: u32,
@@ -246,6 +437,45 @@ pub enum Rendition {
     },
 }
 
+// INLK link to another rendition and where it's placed: the frame of a
+// packed sub-image or the placement of a layer. the key names the linked
+// rendition by attribute, like a facet key
+#[derive(Debug, BinRead, BinWrite, Clone, PartialEq, PartialOrd)]
+#[brw(magic = 0x494E4C4Bu32)]
+pub struct RenditionLink {
+    pub version: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub layout: u16,
+    pub key: KeyToken,
+}
+
+impl RenditionLink {
+    pub fn frame(&self) -> SpriteRect {
+        SpriteRect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn layout(&self) -> LayoutType32 {
+        LayoutType32::from_u32(self.layout as u32)
+    }
+
+    // name identifier of the linked rendition
+    pub fn identifier(&self) -> Option<u16> {
+        self.key
+            .attributes
+            .iter()
+            .find(|attribute| attribute.name == AttributeType16::Identifier)
+            .map(|attribute| attribute.value)
+    }
+}
+
 #[derive(Debug, BinRead, BinWrite, Clone, PartialEq, PartialOrd)]
 pub struct MultisizeImageSetEntry {
     pub width: u32,
@@ -254,56 +484,104 @@ pub struct MultisizeImageSetEntry {
     pub idiom: Idiom,
 }
 
-#[derive(Debug, BinRead, BinWrite, Clone, FromPrimitive, Serialize, PartialEq, PartialOrd)]
+#[derive(Debug, BinRead, BinWrite, Clone, FromPrimitive, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[brw(repr = u16)]
-#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Idiom {
     Universal = 0,
-    Phone,
-    Pad,
-    TV,
-    Car,
-    Watch,
-    Marketing,
+    #[cfg_attr(feature = "serde", serde(rename = "iphone"))]
+    Phone = 1,
+    #[cfg_attr(feature = "serde", serde(rename = "ipad"))]
+    Pad = 2,
+    TV = 3,
+    // CarPlay
+    Car = 4,
+    Watch = 5,
+    Marketing = 6,
+    Mac = 7,
+    Vision = 8,
 }
 
-#[derive(Debug, BinRead, BinWrite, Clone, Copy, Serialize, PartialEq, PartialOrd)]
+#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq, Hash, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[brw(repr = u32)]
-#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum CompressionType {
     Uncompressed = 0,
     RLE,
     ZIP,
     LZVN,
     LZFSE,
-    #[serde(rename = "jpeg-lzfse")]
+    #[cfg_attr(feature = "serde", serde(rename = "jpeg-lzfse"))]
     JPEGLZFSE,
-    Blurred,
+    // the payload holds the blur parameters, see BlurredImage
+    #[cfg_attr(feature = "serde", serde(rename = "blurredimage"))]
+    BlurredImage,
     ASTC,
     // DXTC,
-    #[serde(rename = "palette-img")]
+    #[cfg_attr(feature = "serde", serde(rename = "palette-img"))]
     PaletteImg = 8,
     HEVC,
-    #[serde(rename = "deepmap-lzfse")]
+    #[cfg_attr(feature = "serde", serde(rename = "deepmap-lzfse"))]
     DeepMapLZFSE,
     DeepMap2,
 }
 
-#[derive(Debug, Serialize, FromPrimitive)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum State {
     Normal,
 }
 
 // "Render As" in Xcode
-#[derive(Debug, Serialize, FromPrimitive)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum TemplateMode {
     Automatic = 0, // "Default"
     Original,
     Template,
 }
 
-#[derive(Debug, Serialize, FromPrimitive)]
+// layout direction, 0 stands for both
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Direction {
+    LeftToRight = 1,
+    RightToLeft = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum DisplayGamut {
+    #[cfg_attr(feature = "serde", serde(rename = "sRGB"))]
+    SRGB = 0,
+    #[cfg_attr(feature = "serde", serde(rename = "Display P3"))]
+    P3 = 1,
+}
+
+// 0 stands for any size class
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SizeClass {
+    Compact = 1,
+    Regular = 2,
+}
+
+// how the center of a 3 or 9-part image is filled
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ResizingMode {
+    Tile = 0,
+    Stretch,
+}
+
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Value {
     Off = 0,
     On = 1,
@@ -311,6 +589,15 @@ pub enum Value {
 
 type BGRAColor = u32;
 
+// payload of a BlurredImage compressed rendition: the blur radius in points,
+// followed by an INLK link to the rendition it was blurred from if any
+#[derive(Debug, BinRead, Clone, PartialEq)]
+pub struct BlurredImage {
+    pub radius: f32,
+    #[br(try)]
+    pub source: Option<RenditionLink>,
+}
+
 #[derive(Debug, BinRead, Clone)]
 #[br(import(width: u32, height: u32))]
 #[brw(little, magic = 0xCAFEF00Du32)]
@@ -362,25 +649,67 @@ pub enum LayoutType {
     RecognitionObject = 0x3F6,
 }
 
-// 32 bit version of above
-#[derive(BinRead, BinWrite, Debug, Clone, Copy)]
-#[brw(repr(u32))]
+// 32 bit version of above, layouts added by newer versions of CoreUI are kept
+// as Unknown so the rest of the catalog can still be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LayoutType32 {
-    TextEffect = 0x007,
-    Vector = 0x009,
-    Image = 0x00C, // ???
-    Data = 0x3E8,
-    ExternalLink = 0x3E9,
-    LayerStack = 0x3EA,
-    InternalReference = 0x3EB,
-    PackedImage = 0x3EC,
-    NameList = 0x3ED,
-    UnknownAddObject = 0x3EE,
-    Texture = 0x3EF,
-    TextureImage = 0x3F0,
-    Color = 0x3F1,
-    MultisizeImage = 0x3F2,
-    LayerReference = 0x3F4,
-    ContentRendition = 0x3F5,
-    RecognitionObject = 0x3F6,
+    TextEffect,
+    Vector,
+    Image, // ???
+    Data,
+    ExternalLink,
+    LayerStack,
+    InternalReference,
+    PackedImage,
+    NameList,
+    UnknownAddObject,
+    Texture,
+    TextureImage,
+    Color,
+    MultisizeImage,
+    LayerReference,
+    ContentRendition,
+    RecognitionObject,
+    Unknown(u32),
+}
+
+static LAYOUT_TYPES: [(u32, LayoutType32); 17] = [
+    (0x007, LayoutType32::TextEffect),
+    (0x009, LayoutType32::Vector),
+    (0x00C, LayoutType32::Image),
+    (0x3E8, LayoutType32::Data),
+    (0x3E9, LayoutType32::ExternalLink),
+    (0x3EA, LayoutType32::LayerStack),
+    (0x3EB, LayoutType32::InternalReference),
+    (0x3EC, LayoutType32::PackedImage),
+    (0x3ED, LayoutType32::NameList),
+    (0x3EE, LayoutType32::UnknownAddObject),
+    (0x3EF, LayoutType32::Texture),
+    (0x3F0, LayoutType32::TextureImage),
+    (0x3F1, LayoutType32::Color),
+    (0x3F2, LayoutType32::MultisizeImage),
+    (0x3F4, LayoutType32::LayerReference),
+    (0x3F5, LayoutType32::ContentRendition),
+    (0x3F6, LayoutType32::RecognitionObject),
+];
+
+impl LayoutType32 {
+    pub fn from_u32(raw: u32) -> LayoutType32 {
+        LAYOUT_TYPES
+            .iter()
+            .find(|(value, _)| *value == raw)
+            .map(|(_, layout)| *layout)
+            .unwrap_or(LayoutType32::Unknown(raw))
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            LayoutType32::Unknown(raw) => raw,
+            layout => LAYOUT_TYPES
+                .iter()
+                .find(|(_, known)| *known == layout)
+                .map(|(value, _)| *value)
+                .unwrap_or_default(),
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use crate::error::CarError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteSheetOptions {
+    // scale to use for facets with several scales, falls back to the largest
+    pub scale: u16,
+    // transparent pixels between sprites
+    pub padding: u32,
+    // sprites wrap to a new row past this width
+    pub max_width: u32,
+}
+
+impl Default for SpriteSheetOptions {
+    fn default() -> Self {
+        SpriteSheetOptions {
+            scale: 1,
+            padding: 0,
+            max_width: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// facet name -> location in the sprite sheet
+pub type SpriteMap = BTreeMap<String, SpriteRect>;
+
+#[derive(Debug)]
+pub struct Sprite {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    // RGBA
+    pub pixels: Vec<u8>,
+}
+
+// RGBA atlas
+#[derive(Debug)]
+pub struct SpriteSheet {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl SpriteSheet {
+    // packs the sprites in rows, tallest first, fails if the sheet would be
+    // too large to allocate
+    pub fn pack(
+        mut sprites: Vec<Sprite>,
+        options: &SpriteSheetOptions,
+    ) -> Result<(SpriteSheet, SpriteMap), CarError> {
+        sprites.sort_by(|a, b| b.height.cmp(&a.height).then_with(|| a.name.cmp(&b.name)));
+
+        let mut sprite_map = SpriteMap::new();
+        let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+        for sprite in &sprites {
+            if x > 0 && x + sprite.width > options.max_width {
+                x = 0;
+                y += row_height + options.padding;
+                row_height = 0;
+            }
+            sprite_map.insert(
+                sprite.name.clone(),
+                SpriteRect {
+                    x,
+                    y,
+                    width: sprite.width,
+                    height: sprite.height,
+                },
+            );
+            width = width.max(x + sprite.width);
+            row_height = row_height.max(sprite.height);
+            x += sprite.width + options.padding;
+        }
+        let height = y + row_height;
+
+        let length = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixel_count| pixel_count.checked_mul(4))
+            .ok_or_else(|| {
+                CarError::Parse(format!("sprite sheet of {}x{} is too large", width, height))
+            })?;
+        let mut pixels = vec![0u8; length];
+        for sprite in &sprites {
+            let rect = sprite_map[&sprite.name];
+            let row_length = sprite.width as usize * 4;
+            for (row, sprite_row) in sprite
+                .pixels
+                .chunks_exact(row_length)
+                .take(sprite.height as usize)
+                .enumerate()
+            {
+                let offset = ((rect.y as usize + row) * width as usize + rect.x as usize) * 4;
+                pixels[offset..offset + row_length].copy_from_slice(sprite_row);
+            }
+        }
+
+        Ok((
+            SpriteSheet {
+                width,
+                height,
+                pixels,
+            },
+            sprite_map,
+        ))
+    }
+}
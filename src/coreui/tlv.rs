@@ -1,9 +1,13 @@
 use binrw::BinRead;
+#[cfg(feature = "serde")]
+use serde::Serialize;
 use std::fmt::Debug;
 
 use crate::common;
+use crate::coregraphics;
 
-#[derive(BinRead, Debug, Clone, Copy)]
+#[derive(BinRead, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[br(repr(u32))]
 pub enum EXIFOrientationValue {
     None = 0,
@@ -17,16 +21,65 @@ pub enum EXIFOrientationValue {
     Rotated2700Mirrored = 8,
 }
 
+// one part of a 1, 3 or 9-part image, rows are counted from the top
+#[derive(BinRead, Debug, Clone, Copy, PartialEq)]
+pub struct SliceRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// size of the image covered by all the slices
+fn slices_size(slices: &[SliceRect]) -> (u32, u32) {
+    let width = slices
+        .iter()
+        .map(|slice| slice.x.saturating_add(slice.width))
+        .max();
+    let height = slices
+        .iter()
+        .map(|slice| slice.y.saturating_add(slice.height))
+        .max();
+    (width.unwrap_or(0), height.unwrap_or(0))
+}
+
+// the caps are the slices of the first and last column and row, a single
+// column or row has no caps in that direction
+fn slices_insets(slices: &[SliceRect]) -> coregraphics::EdgeInsets {
+    let mut insets = coregraphics::EdgeInsets::default();
+    let left = slices.iter().min_by_key(|slice| slice.x);
+    let right = slices.iter().max_by_key(|slice| slice.x);
+    if let (Some(left), Some(right)) = (left, right) {
+        if left.x != right.x {
+            insets.left = left.width as f64;
+            insets.right = right.width as f64;
+        }
+    }
+    let top = slices.iter().min_by_key(|slice| slice.y);
+    let bottom = slices.iter().max_by_key(|slice| slice.y);
+    if let (Some(top), Some(bottom)) = (top, bottom) {
+        if top.y != bottom.y {
+            insets.top = top.height as f64;
+            insets.bottom = bottom.height as f64;
+        }
+    }
+    insets
+}
+
 #[derive(BinRead, Clone)]
 pub enum RenditionType {
     #[brw(magic = 0x3E9u32)]
     Slices {
         _length: u32,
-        idk0: u32,
-        idk1: u32,
-        idk2: u32,
-        height: u32,
+        slice_count: u32,
+        #[br(count = slice_count)]
+        slices: Vec<SliceRect>,
+        #[br(calc = slices_size(&slices).0)]
         width: u32,
+        #[br(calc = slices_size(&slices).1)]
+        height: u32,
+        #[br(calc = slices_insets(&slices))]
+        insets: coregraphics::EdgeInsets,
     },
     #[brw(magic = 0x3EBu32)]
     Metrics {
@@ -75,9 +128,14 @@ pub enum RenditionType {
 impl Debug for RenditionType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Slices { height, width, .. } => f.write_fmt(format_args!(
-                "Slice {{ height: {}, width: {} }}",
-                height, width
+            Self::Slices {
+                height,
+                width,
+                insets,
+                ..
+            } => f.write_fmt(format_args!(
+                "Slice {{ height: {}, width: {}, insets: {:?} }}",
+                height, width, insets
             )),
             Self::Metrics { height, width, .. } => f.write_fmt(format_args!(
                 "Metrics {{ height: {}, width: {} }}",
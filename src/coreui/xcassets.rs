@@ -0,0 +1,265 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::common;
+use crate::coregraphics;
+use crate::error::CarError;
+
+use super::contents_json;
+use super::csi;
+use super::rendition;
+use super::tlv;
+use super::CommonAssetStorage;
+use super::Facet;
+
+fn info() -> Value {
+    json!(contents_json::InfoBlock::default())
+}
+
+fn color_space_name(color_space: &coregraphics::ColorSpace) -> &'static str {
+    match color_space {
+        coregraphics::ColorSpace::SRGB => "srgb",
+        coregraphics::ColorSpace::GrayGamma2_2 => "gray-gamma-22",
+        coregraphics::ColorSpace::DisplayP3 => "display-p3",
+        coregraphics::ColorSpace::ExtendedRangeSRGB => "extended-srgb",
+        coregraphics::ColorSpace::ExtendedLinearSRGB => "extended-linear-srgb",
+        coregraphics::ColorSpace::ExtendedGray => "extended-gray",
+        coregraphics::ColorSpace::Unknown => "srgb",
+    }
+}
+
+// idiom and appearances of a rendition, shared by the entries of every kind of set
+fn key_attributes(
+    asset_storage: &CommonAssetStorage,
+    rendition_key: &rendition::Key,
+) -> (&'static str, Vec<contents_json::Appearance>) {
+    let attribute = |attribute| {
+        rendition_key
+            .find_attribute(&asset_storage.renditionkeyfmt, attribute)
+            .unwrap_or(0)
+    };
+    let appearances = contents_json::Appearance::from_name(
+        &asset_storage.appearance_name(attribute(rendition::AttributeType::Appearance)),
+    )
+    .into_iter()
+    .collect();
+    (
+        contents_json::idiom_name(attribute(rendition::AttributeType::Idiom)),
+        appearances,
+    )
+}
+
+fn entry_attributes(asset_storage: &CommonAssetStorage, rendition_key: &rendition::Key) -> Value {
+    let (idiom, appearances) = key_attributes(asset_storage, rendition_key);
+    let mut entry = json!({ "idiom": idiom });
+    if !appearances.is_empty() {
+        entry["appearances"] = json!(appearances);
+    }
+    entry
+}
+
+// picks a file name not used yet by the set
+fn unique_file_name(file_names: &mut BTreeSet<String>, name: &str) -> String {
+    let mut file_name = name.to_string();
+    let mut index = 1;
+    while file_names.contains(&file_name) {
+        file_name = match name.rsplit_once('.') {
+            Some((stem, extension)) => format!("{}-{}.{}", stem, index, extension),
+            None => format!("{}-{}", name, index),
+        };
+        index += 1;
+    }
+    file_names.insert(file_name.clone());
+    file_name
+}
+
+fn write_contents_json(set_path: &Path, contents: &Value) -> Result<()> {
+    fs::create_dir_all(set_path)?;
+    fs::write(
+        set_path.join("Contents.json"),
+        serde_json::to_string_pretty(contents)?,
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetKind {
+    Image,
+    Color,
+    Data,
+}
+
+fn set_kind(layout: rendition::LayoutType32) -> Option<SetKind> {
+    match layout {
+        rendition::LayoutType32::Image
+        | rendition::LayoutType32::PackedImage
+        | rendition::LayoutType32::Vector => Some(SetKind::Image),
+        rendition::LayoutType32::Color => Some(SetKind::Color),
+        rendition::LayoutType32::Data => Some(SetKind::Data),
+        _ => None,
+    }
+}
+
+// facets left out of an exported asset catalog
+#[derive(Debug, PartialEq)]
+pub enum ExportWarning {
+    // no kind of set holds renditions with this layout
+    UnhandledLayout {
+        facet: String,
+        layout: rendition::LayoutType32,
+    },
+    // the renditions of the facet would need different kinds of sets
+    MixedLayouts {
+        facet: String,
+        layouts: Vec<rendition::LayoutType32>,
+    },
+}
+
+impl CommonAssetStorage {
+    // rebuilds an asset catalog in dir, one imageset, colorset or dataset per
+    // facet. facets that can't be exported are skipped and reported
+    pub fn export_xcassets(&self, dir: &Path) -> Result<Vec<ExportWarning>, CarError> {
+        Ok(self.export_sets(dir)?)
+    }
+
+    fn export_sets(&self, dir: &Path) -> Result<Vec<ExportWarning>> {
+        write_contents_json(dir, &json!({ "info": info() }))?;
+        let mut warnings = vec![];
+        for facet in self.facets() {
+            let mut layouts = vec![];
+            for (_, csi_header) in &facet.renditions {
+                if !layouts.contains(&csi_header.csimetadata.layout) {
+                    layouts.push(csi_header.csimetadata.layout);
+                }
+            }
+            let set_kinds: Vec<Option<SetKind>> =
+                layouts.iter().map(|layout| set_kind(*layout)).collect();
+            match set_kinds.as_slice() {
+                [] => continue,
+                [first, rest @ ..] if rest.iter().any(|kind| kind != first) => {
+                    warnings.push(ExportWarning::MixedLayouts {
+                        facet: facet.name.to_string(),
+                        layouts,
+                    })
+                }
+                [Some(SetKind::Image), ..] => self.export_image_set(&facet, dir)?,
+                [Some(SetKind::Color), ..] => self.export_color_set(&facet, dir)?,
+                [Some(SetKind::Data), ..] => self.export_data_set(&facet, dir)?,
+                [None, ..] => warnings.push(ExportWarning::UnhandledLayout {
+                    facet: facet.name.to_string(),
+                    layout: layouts[0],
+                }),
+            }
+        }
+        Ok(warnings)
+    }
+
+    fn export_image_set(&self, facet: &Facet, dir: &Path) -> Result<()> {
+        let set_path = csi::output_path_for_name(dir, &format!("{}.imageset", facet.name))?;
+        fs::create_dir_all(&set_path)?;
+        let mut file_names = BTreeSet::new();
+        let appearancedb = self.appearancedb.clone().unwrap_or_default();
+        let mut images = vec![];
+        for (rendition_key, csi_header) in &facet.renditions {
+            let file_name = unique_file_name(&mut file_names, &csi_header.csimetadata.name());
+            let file_path = csi::output_path_for_name(&set_path, &file_name)?;
+            match &csi_header.rendition_data {
+                // stored as is, e.g. JPEGs
+                Some(rendition::Rendition::RawData { raw_data, .. }) => {
+                    fs::write(&file_path, &raw_data.0)?
+                }
+                Some(rendition::Rendition::Vector { pdf_data, .. }) => {
+                    fs::write(&file_path, &pdf_data.0)?
+                }
+                _ => csi_header
+                    .write_png(fs::File::create(&file_path)?)
+                    .context(format!("Unable to export {:?}", file_name))?,
+            }
+
+            images.push(contents_json::ImageEntry {
+                filename: file_name,
+                ..csi_header.to_contents_entry(rendition_key, &self.renditionkeyfmt, &appearancedb)
+            });
+        }
+        let mut contents = contents_json::ContentsJson::new(images);
+        // a PDF is used for every scale
+        if contents.images.iter().any(|image| image.scale.is_none()) {
+            contents.properties = Some(contents_json::Properties {
+                preserves_vector_representation: true,
+            });
+        }
+        write_contents_json(&set_path, &json!(contents))
+    }
+
+    fn export_color_set(&self, facet: &Facet, dir: &Path) -> Result<()> {
+        let mut colors = vec![];
+        for (rendition_key, csi_header) in &facet.renditions {
+            let (flags, components) = match &csi_header.rendition_data {
+                Some(rendition::Rendition::Color {
+                    flags, components, ..
+                }) => (flags, components),
+                _ => continue,
+            };
+            let component = |value: &f64| format!("{:.3}", value);
+            let components = match components.as_slice() {
+                [red, green, blue, alpha] => json!({
+                    "red": component(red),
+                    "green": component(green),
+                    "blue": component(blue),
+                    "alpha": component(alpha),
+                }),
+                [white, alpha] => json!({
+                    "white": component(white),
+                    "alpha": component(alpha),
+                }),
+                _ => continue,
+            };
+
+            let mut color = entry_attributes(self, rendition_key);
+            color["color"] = json!({
+                "color-space": color_space_name(&flags.color_space()),
+                "components": components,
+            });
+            colors.push(color);
+        }
+        let set_path = csi::output_path_for_name(dir, &format!("{}.colorset", facet.name))?;
+        write_contents_json(&set_path, &json!({ "colors": colors, "info": info() }))
+    }
+
+    fn export_data_set(&self, facet: &Facet, dir: &Path) -> Result<()> {
+        let set_path = csi::output_path_for_name(dir, &format!("{}.dataset", facet.name))?;
+        fs::create_dir_all(&set_path)?;
+        let mut file_names = BTreeSet::new();
+        let mut data = vec![];
+        for (rendition_key, csi_header) in &facet.renditions {
+            let file_name = unique_file_name(&mut file_names, &csi_header.csimetadata.name());
+            // stored compressed by newer versions of actool
+            let mut file = fs::File::create(csi::output_path_for_name(&set_path, &file_name)?)?;
+            csi_header
+                .write_data(&mut file)
+                .context(format!("Unable to export {:?}", file_name))?;
+
+            let mut entry = entry_attributes(self, rendition_key);
+            entry["filename"] = json!(file_name);
+            let uti = csi_header
+                .properties()
+                .into_iter()
+                .find_map(|rendition_type| match rendition_type {
+                    tlv::RenditionType::UTI { string, .. } => {
+                        Some(common::parse_padded_string(&string))
+                    }
+                    _ => None,
+                });
+            if let Some(uti) = uti {
+                entry["universal-type-identifier"] = json!(uti);
+            }
+            data.push(entry);
+        }
+        write_contents_json(&set_path, &json!({ "data": data, "info": info() }))
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::io;
+
+// errors from parsing an Assets.car file, so callers can tell a corrupt file
+// apart from an I/O failure
+#[derive(Debug)]
+pub enum CarError {
+    Io(io::Error),
+    // found holds the debug representation of the bytes read at pos
+    BadMagic { pos: u64, found: String },
+    UnsupportedLayout(u16),
+    TlvParse(String),
+    Truncated,
+    UnknownAttributeType(u16),
+    // the key format claims more attributes than it holds
+    KeyFormatMismatch { max_count: u32, available: u32 },
+    // the rendition holds data or a color rather than a bitmap
+    NotAnImage,
+    // facet keys of this schema version can't be decoded
+    UnsupportedSchema(u32),
+    // the bitmap needs expected bytes but only actual were decoded
+    SizeMismatch { expected: u64, actual: u64 },
+    // any other malformed structure
+    Parse(String),
+}
+
+impl fmt::Display for CarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CarError::Io(err) => write!(f, "I/O error: {}", err),
+            CarError::BadMagic { pos, found } => {
+                write!(f, "bad magic at 0x{:X}: found {}", pos, found)
+            }
+            CarError::UnsupportedLayout(layout) => {
+                write!(f, "unsupported rendition layout 0x{:X}", layout)
+            }
+            CarError::TlvParse(message) => write!(f, "unable to parse TLV: {}", message),
+            CarError::Truncated => write!(f, "unexpected end of file"),
+            CarError::UnknownAttributeType(attribute_type) => {
+                write!(f, "unknown rendition attribute type {}", attribute_type)
+            }
+            CarError::KeyFormatMismatch {
+                max_count,
+                available,
+            } => write!(
+                f,
+                "key format has {} attributes but room for {}",
+                max_count, available
+            ),
+            CarError::NotAnImage => write!(f, "rendition is not an image"),
+            CarError::UnsupportedSchema(schema_version) => {
+                write!(f, "unsupported catalog schema version {}", schema_version)
+            }
+            CarError::SizeMismatch { expected, actual } => write!(
+                f,
+                "size mismatch: bitmap needs {} bytes but {} were decoded",
+                expected, actual
+            ),
+            CarError::Parse(message) => write!(f, "unable to parse: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CarError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CarError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => CarError::Truncated,
+            _ => CarError::Io(err),
+        }
+    }
+}
+
+impl From<binrw::Error> for CarError {
+    fn from(err: binrw::Error) -> Self {
+        match err {
+            binrw::Error::Io(err) => err.into(),
+            binrw::Error::BadMagic { pos, found } => CarError::BadMagic {
+                pos,
+                found: format!("{:?}", found),
+            },
+            // errors raised by our own parsers, e.g. from try_map
+            binrw::Error::Custom { err, .. } => match err.downcast::<CarError>() {
+                Ok(err) => *err,
+                Err(err) => CarError::Parse(err.to_string()),
+            },
+            binrw::Error::Backtrace(backtrace) => (*backtrace.error).into(),
+            err => CarError::Parse(err.to_string()),
+        }
+    }
+}
+
+impl From<png::EncodingError> for CarError {
+    fn from(err: png::EncodingError) -> Self {
+        match err {
+            png::EncodingError::IoError(err) => err.into(),
+            err => CarError::Parse(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for CarError {
+    fn from(err: serde_json::Error) -> Self {
+        if err.is_io() {
+            io::Error::from(err).into()
+        } else {
+            CarError::Parse(err.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<plist::Error> for CarError {
+    fn from(err: plist::Error) -> Self {
+        match err.into_io() {
+            Ok(err) => err.into(),
+            Err(err) => CarError::Parse(err.to_string()),
+        }
+    }
+}
+
+// keeps the kind of errors passed through anyhow by the BOM reader
+impl From<anyhow::Error> for CarError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<CarError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<binrw::Error>() {
+            Ok(err) => return err.into(),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(err) => err.into(),
+            Err(err) => CarError::Parse(format!("{:#}", err)),
+        }
+    }
+}
@@ -1,5 +1,7 @@
+#[cfg(feature = "serde")]
 pub mod assetutil;
 pub mod bom;
 pub mod common;
 pub mod coregraphics;
 pub mod coreui;
+pub mod error;
@@ -6,7 +6,7 @@ use clap::CommandFactory;
 use clap::Parser;
 use clap::Subcommand;
 
-use assetutil::ToAssetUtilHeader;
+use assetutil::ToAssetUtilJson;
 
 mod actool;
 mod assetutil;
@@ -14,6 +14,7 @@ mod bom;
 mod common;
 mod coregraphics;
 mod coreui;
+mod error;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -77,28 +78,7 @@ fn main() -> Result<()> {
             if let Some(car_path) = info {
                 let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
 
-                let asset_util_header = serde_json::to_value(car.asset_util_header())?;
-                let mut result: Vec<serde_json::Value> = vec![asset_util_header];
-
-                let mut entries =
-                    assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
-                entries.sort_by(|a, b| {
-                    (
-                        a.asset_type.clone(),
-                        a.name.clone(),
-                        a.rendition_name.clone(),
-                    )
-                        .cmp(&(
-                            b.asset_type.clone(),
-                            b.name.clone(),
-                            b.rendition_name.clone(),
-                        ))
-                });
-                for entry in entries {
-                    let value = serde_json::to_value(entry)?;
-                    result.push(value);
-                }
-
+                let result = car.to_assetutil_json();
                 let json = serde_json::to_string_pretty(&result)?;
                 println!("{}", json);
                 Ok(())
@@ -137,13 +117,13 @@ fn main() -> Result<()> {
         }
         Commands::Debug { car_path } => {
             let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
-            dbg!(car.theme_store.store.header);
-            dbg!(car.theme_store.store.extended_metadata);
-            dbg!(car.theme_store.store.renditionkeyfmt);
-            dbg!(car.theme_store.store.appearancedb);
-            dbg!(car.theme_store.store.bitmapkeydb);
-            dbg!(car.theme_store.store.facetkeysdb);
-            dbg!(car.theme_store.store.imagedb);
+            println!("{:#?}", car.theme_store.store.header);
+            println!("{:#?}", car.theme_store.store.extended_metadata);
+            println!("{:#?}", car.theme_store.store.renditionkeyfmt);
+            println!("{:#?}", car.theme_store.store.appearancedb);
+            println!("{:#?}", car.theme_store.store.bitmapkeydb);
+            println!("{:#?}", car.theme_store.store.facetkeysdb);
+            println!("{:#?}", car.theme_store.store.imagedb);
             Ok(())
         }
     }
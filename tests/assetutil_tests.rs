@@ -1,5 +1,10 @@
 use carutil_lib::assetutil;
+use carutil_lib::assetutil::ToAssetUtilCsv;
 use carutil_lib::assetutil::ToAssetUtilHeader;
+use carutil_lib::assetutil::ToAssetUtilJson;
+use carutil_lib::assetutil::ToAssetUtilPlist;
+use carutil_lib::common;
+use carutil_lib::coregraphics;
 use carutil_lib::coreui;
 
 use assert_json_diff::assert_json_eq;
@@ -7,7 +12,10 @@ use assert_json_diff::assert_json_matches;
 use assert_json_diff::CompareMode;
 use assert_json_diff::Config;
 use assert_json_diff::NumericMode;
+use binrw::BinRead;
 use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Cursor;
 
 // test file from https://blog.timac.org/2018/1018-reverse-engineering-the-car-file-format/
 static CAR_PATH: &str = "./tests/Assets.car";
@@ -16,6 +24,7 @@ static CAR_PATH: &str = "./tests/Assets.car";
 fn header_simple() {
     let expected_header = json!({
       "AssetStorageVersion": "IBCocoaTouchImageCatalogTool-10.0",
+      "AssociatedChecksum": 2039897368,
       "Authoring Tool": "@(#)PROGRAM:CoreThemeDefinition  PROJECT:CoreThemeDefinition-346.29\n",
       "CoreUIVersion": 498,
       "DumpToolVersion": 804.3,
@@ -44,7 +53,8 @@ fn header_simple() {
       "PlatformVersion": "12.0",
       "SchemaVersion": 2,
       "StorageVersion": 15,
-      "Timestamp": 1539543253
+      "Timestamp": 1539543253,
+      "UUID": "9EA56D07-3242-4F88-8BC1-C16C25EA65F2"
     });
 
     let asset_storage =
@@ -54,6 +64,27 @@ fn header_simple() {
     assert_json_eq!(header, expected_header);
 }
 
+#[test]
+fn header_thinning_parameters() {
+    let mut asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let header = serde_json::to_value(asset_storage.asset_util_header())
+        .expect("Unable to serialize to JSON value");
+    assert!(header.get("ThinningParameters").is_none());
+
+    let thinning_arguments = b"optimized <idiom:phone> <scale:3>";
+    let extended_metadata = &mut asset_storage.theme_store.store.extended_metadata;
+    extended_metadata.thinning_arguments = [b' '; 256];
+    extended_metadata.thinning_arguments[..thinning_arguments.len()]
+        .copy_from_slice(thinning_arguments);
+    let header = serde_json::to_value(asset_storage.asset_util_header())
+        .expect("Unable to serialize to JSON value");
+    assert_eq!(
+        header["ThinningParameters"],
+        "optimized <idiom:phone> <scale:3>"
+    );
+}
+
 #[test]
 fn color_simple() {
     let expected_color = json!({
@@ -193,3 +224,969 @@ fn image_simple() {
 
     assert_json_eq!(image, expected_image);
 }
+
+fn image_csi_header(
+    compression_type: coreui::rendition::CompressionType,
+    payload: &[u8],
+) -> coreui::csi::Header {
+    coreui::csi::Header {
+        version: 1,
+        rendition_flags: coreui::csi::RenditionFlags(0),
+        width: 1,
+        height: 1,
+        scale_factor: 100,
+        pixel_format: coreui::csi::PixelFormat::ARGB,
+        color_space: coreui::csi::ColorModel(1),
+        csimetadata: coreui::csi::Metadata {
+            mod_time: 0,
+            layout: coreui::rendition::LayoutType32::Image,
+            name: common::str_to_sized_slice128("Image.png"),
+        },
+        csibitmaplist: coreui::csi::BitmapList {
+            tlv_length: 0,
+            bitmap_count: 1,
+            zero: 0,
+            rendition_length: payload.len() as u32 + 16,
+        },
+        tlv_data: common::RawData(vec![]),
+        rendition_data: Some(coreui::rendition::Rendition::Theme {
+            version: 0,
+            compression_type,
+            _raw_data_length: payload.len() as u32,
+            raw_data: common::RawData(payload.to_vec()),
+        }),
+        endian: binrw::Endian::Little,
+    }
+}
+
+#[test]
+fn image_hevc() {
+    let mut payload = vec![0u8; 8];
+    payload.extend_from_slice(b"\0\0\0\x18ftypheic");
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::HEVC, &payload);
+    assert_eq!(csi_header.raw_bytes(), Some(&payload[8..]));
+
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyHEIC".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(image["Compression"], "hevc");
+}
+
+#[test]
+fn data_scale() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    for name in ["MyText", "MyPDF"] {
+        let asset = entries
+            .iter()
+            .find(|e| e.name == Some(name.to_string()))
+            .expect("No rendition found");
+        assert_eq!(asset.scale, Some(1));
+    }
+
+    // scale-agnostic data still reports a scale of 1
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::Uncompressed, b"");
+    csi_header.scale_factor = 0;
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Data;
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyData".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let data = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(data["Scale"], 1);
+}
+
+#[test]
+fn entry_builder() {
+    let expected_color = json!({
+      "AssetType": "Color",
+      "Color components": [1.0, 0.0, 0.0, 0.5],
+      "Colorspace": "srgb",
+      "Idiom": "universal",
+      "Name": "MyColor",
+      "NameIdentifier": 44959,
+      "Scale": 1,
+      "SizeOnDisk": 260,
+      "State": "Normal",
+      "Value": "Off"
+    });
+
+    let entry = assetutil::AssetUtilEntry::builder()
+        .asset_type("Color")
+        .color_components(vec![1.0, 0.0, 0.0, 0.5])
+        .colorspace(coregraphics::ColorSpace::SRGB)
+        .idiom(coreui::rendition::Idiom::Universal)
+        .name("MyColor")
+        .name_identifier(44959)
+        .scale(1)
+        .size_on_disk(260)
+        .state(coreui::rendition::State::Normal)
+        .value(coreui::rendition::Value::Off)
+        .build();
+    let color = serde_json::to_value(entry).expect("Unable to serialize output");
+
+    assert_json_eq!(color, expected_color);
+}
+
+#[test]
+fn image_opacity() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    assert!(entries.iter().all(|entry| entry.opacity.is_none()));
+
+    // BlendModeAndOpacity TLV with blend mode 0 and opacity 0.5
+    let tlv_data = vec![0xEC, 0x03, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x3F];
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::Uncompressed, b"");
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyTranslucentImage".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(image["Opaque"], false);
+    assert_eq!(image["Opacity"], 0.5);
+}
+
+#[test]
+fn empty_color_components() {
+    let expected_color = json!({
+      "AssetType": "Color",
+      "Name": "MyColor",
+    });
+
+    let entry = assetutil::AssetUtilEntry::builder()
+        .asset_type("Color")
+        .color_components(vec![])
+        .name("MyColor")
+        .build();
+    let color = serde_json::to_value(entry).expect("Unable to serialize output");
+
+    assert_json_eq!(color, expected_color);
+}
+
+#[test]
+fn write_csv() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let mut csv = vec![];
+    asset_storage
+        .theme_store
+        .store
+        .write_csv(&mut csv)
+        .expect("Unable to write CSV");
+    let csv = String::from_utf8(csv).expect("Invalid CSV");
+    let rows: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(rows.len(), 8);
+    assert_eq!(
+        rows[0],
+        "Name,AssetType,Idiom,Scale,PixelWidth,PixelHeight,Compression,SizeOnDisk,SHA1Digest"
+    );
+    assert!(rows.contains(
+        &"MyPNG,Image,universal,3,84,84,palette-img,1961,3F7342D3BD5E83979F101C11E58F1ACC61E983EA56881A139D7ACC711A5D1193"
+    ));
+}
+
+#[test]
+fn to_assetutil_json() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let json = asset_storage.to_assetutil_json();
+    let json = json.as_array().expect("Not an array");
+    assert_eq!(
+        json[0],
+        serde_json::to_value(asset_storage.asset_util_header()).expect("Invalid header")
+    );
+
+    // sorted by name, idiom, scale and appearance
+    let entries: Vec<(&str, u64)> = json[1..]
+        .iter()
+        .map(|entry| {
+            (
+                entry["Name"].as_str().expect("No name"),
+                entry["Scale"].as_u64().expect("No scale"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("MyColor", 1),
+            ("MyJPG", 1),
+            ("MyPDF", 1),
+            ("MyPNG", 1),
+            ("MyPNG", 2),
+            ("MyPNG", 3),
+            ("MyText", 1),
+        ]
+    );
+    assert_eq!(json, asset_storage.to_assetutil_json().as_array().unwrap());
+}
+
+#[test]
+fn write_ndjson() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let mut ndjson = vec![];
+    asset_storage
+        .write_ndjson(&mut ndjson)
+        .expect("Unable to write NDJSON");
+    let ndjson = String::from_utf8(ndjson).expect("Invalid UTF-8");
+    let lines: Vec<serde_json::Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Invalid JSON line"))
+        .collect();
+    assert!(ndjson.ends_with('\n'));
+
+    let mut expected =
+        vec![serde_json::to_value(asset_storage.asset_util_header()).expect("Invalid header")];
+    for entry in
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store)
+    {
+        expected.push(serde_json::to_value(entry).expect("Unable to serialize output"));
+    }
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn to_plist() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    for format in [assetutil::PlistFormat::Xml, assetutil::PlistFormat::Binary] {
+        let mut data = vec![];
+        asset_storage
+            .to_plist(&mut data, format)
+            .expect("Unable to write plist");
+        match format {
+            assetutil::PlistFormat::Xml => assert!(data.starts_with(b"<?xml")),
+            assetutil::PlistFormat::Binary => assert!(data.starts_with(b"bplist00")),
+        }
+
+        // same structure as the JSON output
+        let plist = plist::Value::from_reader(Cursor::new(data)).expect("Invalid plist");
+        let json = serde_json::to_value(&plist).expect("Unable to convert plist");
+        assert_json_matches!(
+            json,
+            asset_storage.to_assetutil_json(),
+            Config::new(CompareMode::Strict).numeric_mode(NumericMode::AssumeFloat)
+        );
+    }
+}
+
+#[test]
+fn write_assetutil_json() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let mut json = vec![];
+    asset_storage
+        .write_assetutil_json(&mut json)
+        .expect("Unable to write JSON");
+    let streamed: serde_json::Value = serde_json::from_slice(&json).expect("Invalid JSON");
+
+    assert_eq!(streamed.as_array().map(Vec::len), Some(8));
+    assert_eq!(streamed, asset_storage.to_assetutil_json());
+}
+
+#[test]
+fn image_blurred() {
+    let csi_header = image_csi_header(
+        coreui::rendition::CompressionType::BlurredImage,
+        &4.5f32.to_le_bytes(),
+    );
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyBlurredImage".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(image["Compression"], "blurredimage");
+    assert_eq!(image["BlurRadius"], 4.5);
+
+    // other compression types have no blur radius
+    let csi_header = image_csi_header(
+        coreui::rendition::CompressionType::Uncompressed,
+        &4.5f32.to_le_bytes(),
+    );
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyImage".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert!(image.get("BlurRadius").is_none());
+
+    // compression type 6
+    let compression_type =
+        coreui::rendition::CompressionType::read_le(&mut Cursor::new(6u32.to_le_bytes()))
+            .expect("Unable to parse compression type");
+    assert_eq!(
+        compression_type,
+        coreui::rendition::CompressionType::BlurredImage
+    );
+}
+
+#[test]
+fn image_bits_per_component() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let bits_per_component = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyImage".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["BitsPerComponent"].clone()
+    };
+    assert_eq!(bits_per_component(&csi_header), 8);
+
+    csi_header.pixel_format = coreui::csi::PixelFormat::ARGB16;
+    assert_eq!(csi_header.bits_per_component(), 16);
+    assert_eq!(bits_per_component(&csi_header), 16);
+}
+
+#[test]
+fn image_colorspace() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let colorspace = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyImage".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["Colorspace"].clone()
+    };
+    assert_eq!(colorspace(&csi_header), "srgb");
+
+    csi_header.color_space = coreui::csi::ColorModel(3);
+    assert_eq!(colorspace(&csi_header), "p3");
+
+    csi_header.color_space = coreui::csi::ColorModel(2);
+    assert_eq!(colorspace(&csi_header), "gray gamma 22");
+}
+
+#[test]
+fn image_color_model() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let color_model = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyImage".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["ColorModel"].clone()
+    };
+    assert_eq!(color_model(&csi_header), "RGB");
+
+    // extended gray
+    csi_header.color_space = coreui::csi::ColorModel(6);
+    assert_eq!(color_model(&csi_header), "Monochrome");
+    assert_eq!(
+        serde_json::to_value(coregraphics::ColorSpace::ExtendedGray).unwrap(),
+        "extended gray"
+    );
+}
+
+#[test]
+fn image_appearance() {
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let appearancedb = BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("NSAppearanceNameDarkAqua".to_string(), 1),
+    ]);
+    let appearance = |appearance: u16| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("MyImage".to_string()),
+            vec![(coreui::rendition::AttributeType::Appearance, appearance)],
+            vec![],
+            &appearancedb,
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["Appearance"].clone()
+    };
+    assert_eq!(appearance(1), "NSAppearanceNameDarkAqua");
+    // the default appearance is omitted
+    assert_eq!(appearance(0), serde_json::Value::Null);
+}
+
+// slices TLV of a 30x20 9-part image with insets 4, 5, 6, 7 (top, left, bottom, right)
+fn nine_part_slices_tlv() -> Vec<u8> {
+    let columns = [(0u32, 5u32), (5, 18), (23, 7)];
+    let rows = [(0u32, 4u32), (4, 10), (14, 6)];
+    let mut tlv = 0x3E9u32.to_le_bytes().to_vec();
+    tlv.extend_from_slice(&(4 + 9 * 16u32).to_le_bytes());
+    tlv.extend_from_slice(&9u32.to_le_bytes());
+    for (y, height) in rows {
+        for (x, width) in columns {
+            for value in [x, y, width, height] {
+                tlv.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    tlv
+}
+
+#[test]
+fn image_resizable() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let resizable_image = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyButton".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["ResizableImage"].clone()
+    };
+    assert_eq!(resizable_image(&csi_header), serde_json::Value::Null);
+
+    let tlv = nine_part_slices_tlv();
+    csi_header.csibitmaplist.tlv_length = tlv.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv);
+    csi_header.rendition_flags = coreui::csi::RenditionFlags(0x2 | 0x8);
+    assert_eq!(
+        resizable_image(&csi_header),
+        json!({
+            "capInsets": { "top": 4.0, "left": 5.0, "bottom": 6.0, "right": 7.0 },
+            "resizingMode": "stretch",
+        })
+    );
+
+    csi_header.rendition_flags = coreui::csi::RenditionFlags(0x2);
+    assert_eq!(resizable_image(&csi_header)["resizingMode"], "tile");
+}
+
+#[test]
+fn image_properties() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let image = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyImage".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")
+    };
+    let keys = [
+        "AlphaCropped",
+        "AlphaCroppedFrame",
+        "EXIFOrientation",
+        "Flippable",
+        "Tintable",
+        "Vector Based",
+    ];
+    for key in keys {
+        assert_eq!(image(&csi_header)[key], serde_json::Value::Null);
+    }
+
+    // metrics of a 30x40 image cropped by 2/3 top left and 4/5 bottom right,
+    // then an EXIF orientation rotated by 90 degrees
+    let mut tlv_data = vec![];
+    for value in [0x3EB, 28, 1, 2, 3, 4, 5, 40, 30u32, 0x3EE, 4, 5] {
+        tlv_data.extend_from_slice(&value.to_le_bytes());
+    }
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+    csi_header.rendition_flags = coreui::csi::RenditionFlags(0x1 | 0x200 | 0x400);
+
+    let image = image(&csi_header);
+    assert_eq!(image["AlphaCropped"], true);
+    assert_eq!(
+        image["AlphaCroppedFrame"],
+        json!({
+            "origin": { "x": 2.0, "y": 3.0 },
+            "size": { "width": 24.0, "height": 32.0 },
+        })
+    );
+    assert_eq!(image["EXIFOrientation"], "Rotated90");
+    assert_eq!(image["Flippable"], true);
+    assert_eq!(image["Tintable"], true);
+    assert_eq!(image["Vector Based"], true);
+}
+
+#[test]
+fn image_opaque() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    let mut checked = 0;
+    for entry in &entries {
+        let expected = match entry.name.as_deref() {
+            Some("MyPNG") => false,
+            Some("MyJPG") => true,
+            _ => continue,
+        };
+        assert_eq!(entry.opaque, Some(expected));
+        checked += 1;
+    }
+    assert_eq!(checked, 4);
+
+    // an undecodable palette falls back to the flags instead of panicking
+    let mut csi_header =
+        image_csi_header(coreui::rendition::CompressionType::PaletteImg, b"garbage");
+    csi_header.rendition_flags = coreui::csi::RenditionFlags(0x10);
+    assert!(csi_header.is_opaque());
+
+    // translucent whatever the flags say
+    let mut tlv_data = 0x3ECu32.to_le_bytes().to_vec();
+    tlv_data.extend_from_slice(&8u32.to_le_bytes());
+    tlv_data.extend_from_slice(&0f32.to_le_bytes());
+    tlv_data.extend_from_slice(&0.5f32.to_le_bytes());
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+    assert!(!csi_header.is_opaque());
+}
+
+#[test]
+fn image_bitmap_encoding() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    assert!(entries.iter().all(|entry| entry.bitmap_encoding.is_none()));
+    let encoding = |name: &str| {
+        asset_storage
+            .theme_store
+            .store
+            .imagedb
+            .values()
+            .find(|csi_header| csi_header.csimetadata.name() == name)
+            .expect("No rendition found")
+            .bitmap_encoding()
+    };
+    assert_eq!(encoding("Timac.png"), coreui::csi::BitmapEncoding::RGB);
+    assert_eq!(encoding("TimacJPG.jpg"), coreui::csi::BitmapEncoding::JPEG);
+    assert_eq!(encoding("text.txt"), coreui::csi::BitmapEncoding::None);
+
+    let image = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("MyImage".to_string()),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["BitmapEncoding"].clone()
+    };
+    // the pixel format is ARGB
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    assert_eq!(
+        csi_header.bitmap_encoding(),
+        coreui::csi::BitmapEncoding::RGB
+    );
+    assert_eq!(image(&csi_header), serde_json::Value::Null);
+
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::HEVC, &[]);
+    assert_eq!(
+        csi_header.bitmap_encoding(),
+        coreui::csi::BitmapEncoding::HEIF
+    );
+    assert_eq!(image(&csi_header), "HEIF");
+
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::JPEGLZFSE, &[]);
+    assert_eq!(
+        csi_header.bitmap_encoding(),
+        coreui::csi::BitmapEncoding::JPEG
+    );
+    assert_eq!(image(&csi_header), "JPEG");
+
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    csi_header.pixel_format = coreui::csi::PixelFormat::Gray16;
+    assert_eq!(
+        csi_header.bitmap_encoding(),
+        coreui::csi::BitmapEncoding::Gray
+    );
+    assert_eq!(image(&csi_header), serde_json::Value::Null);
+}
+
+#[test]
+fn image_idiom() {
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let idiom = |value: u16| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("MyImage".to_string()),
+            vec![(coreui::rendition::AttributeType::Idiom, value)],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["Idiom"].clone()
+    };
+    let expected = [
+        "universal",
+        "iphone",
+        "ipad",
+        "tv",
+        "car",
+        "watch",
+        "marketing",
+        "mac",
+        "vision",
+    ];
+    for (value, expected) in expected.into_iter().enumerate() {
+        assert_eq!(idiom(value as u16), expected);
+    }
+    assert_eq!(idiom(42), 42);
+}
+
+#[test]
+fn image_display_gamut() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    assert!(entries.iter().all(|entry| entry.display_gamut.is_none()));
+
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let display_gamut = |value: u16| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("MyImage".to_string()),
+            vec![(coreui::rendition::AttributeType::DisplayGamut, value)],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["DisplayGamut"].clone()
+    };
+    assert_eq!(display_gamut(0), serde_json::Value::Null);
+    assert_eq!(display_gamut(1), "Display P3");
+    assert_eq!(display_gamut(7), 7);
+
+    // with a Display P3 variant of Timac.png, the sRGB images of MyPNG report
+    // their gamut too
+    let mut asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &mut asset_storage.theme_store.store;
+    let gamut_position = store
+        .renditionkeyfmt
+        .attribute_types
+        .iter()
+        .position(|attribute_type| {
+            *attribute_type == coreui::rendition::AttributeType::DisplayGamut
+        })
+        .expect("No DisplayGamut attribute");
+    let (rendition_key, csi_header) = store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "Timac.png")
+        .map(|(rendition_key, csi_header)| (*rendition_key, csi_header.clone()))
+        .expect("No rendition found");
+    let mut p3_rendition_key = rendition_key;
+    p3_rendition_key.raw[gamut_position] = 1;
+    store.imagedb.insert(p3_rendition_key, csi_header);
+
+    let mut display_gamuts = assetutil::AssetUtilEntry::entries_from_asset_storage(store)
+        .into_iter()
+        .map(|entry| serde_json::to_value(entry).expect("Unable to serialize output"))
+        .filter(|entry| !entry["DisplayGamut"].is_null())
+        .map(|entry| json!([entry["Name"], entry["DisplayGamut"]]))
+        .collect::<Vec<_>>();
+    display_gamuts.sort_by_key(|display_gamut| display_gamut.to_string());
+    assert_eq!(
+        display_gamuts,
+        [
+            json!(["MyPNG", "Display P3"]),
+            json!(["MyPNG", "sRGB"]),
+            json!(["MyPNG", "sRGB"]),
+            json!(["MyPNG", "sRGB"]),
+        ]
+    );
+}
+
+#[test]
+fn image_size_class() {
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let image = |horizontal: u16, vertical: u16| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("MyImage".to_string()),
+            vec![
+                (
+                    coreui::rendition::AttributeType::SizeClassHorizontal,
+                    horizontal,
+                ),
+                (
+                    coreui::rendition::AttributeType::SizeClassVertical,
+                    vertical,
+                ),
+            ],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")
+    };
+
+    let any = image(0, 0);
+    assert_eq!(any["SizeClassHorizontal"], serde_json::Value::Null);
+    assert_eq!(any["SizeClassVertical"], serde_json::Value::Null);
+
+    let compact_regular = image(1, 2);
+    assert_eq!(compact_regular["SizeClassHorizontal"], "compact");
+    assert_eq!(compact_regular["SizeClassVertical"], "regular");
+
+    assert_eq!(image(3, 0)["SizeClassHorizontal"], 3);
+}
+
+#[test]
+fn image_direction() {
+    // MyPNG renditions are used for both directions
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    assert!(entries.iter().all(|entry| entry.direction.is_none()));
+
+    let csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    let direction = |value: u16| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("MyArrow".to_string()),
+            vec![(coreui::rendition::AttributeType::Direction, value)],
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).expect("Unable to serialize output")["Direction"].clone()
+    };
+    assert_eq!(direction(0), serde_json::Value::Null);
+    assert_eq!(direction(1), "left-to-right");
+    assert_eq!(direction(2), "right-to-left");
+}
+
+#[test]
+fn color_size_on_disk() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let csi_header = asset_storage
+        .theme_store
+        .store
+        .imagedb
+        .values()
+        .find(|csi_header| csi_header.csimetadata.name() == "MyColor")
+        .expect("No rendition found");
+    let component_count = match &csi_header.rendition_data {
+        Some(coreui::rendition::Rendition::Color {
+            component_count, ..
+        }) => *component_count,
+        _ => panic!("MyColor isn't a color"),
+    };
+
+    // the color rendition is a 16 byte header followed by the components as f64
+    assert_eq!(
+        csi_header.csibitmaplist.rendition_length,
+        16 + 8 * component_count
+    );
+    // 260 in assetutil's output
+    assert_eq!(
+        csi_header.size_on_disk(),
+        184 + csi_header.csibitmaplist.tlv_length + 16 + 8 * component_count
+    );
+    assert_eq!(csi_header.size_on_disk(), 260);
+}
+
+#[test]
+fn data_uti() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    let uti = |name: &str| {
+        entries
+            .iter()
+            .find(|e| e.name == Some(name.to_string()))
+            .and_then(|e| e.uti.clone())
+    };
+    assert_eq!(uti("MyPDF").as_deref(), Some("com.adobe.pdf"));
+    // text.txt has no UTI TLV, assetutil reports it as unknown
+    assert_eq!(uti("MyText").as_deref(), Some("UTI-Unknown"));
+
+    // string_length counts the NUL padding
+    let string = b"public.plain-text\0\0\0";
+    let mut tlv_data = 0x3EDu32.to_le_bytes().to_vec();
+    tlv_data.extend_from_slice(&(string.len() as u32 + 8).to_le_bytes());
+    tlv_data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+    tlv_data.extend_from_slice(&0u32.to_le_bytes());
+    tlv_data.extend_from_slice(string);
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::Uncompressed, b"");
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Data;
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyText".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    assert_eq!(entry.uti.as_deref(), Some("public.plain-text"));
+}
+
+#[test]
+fn vector_asset_type() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Vector;
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Vector {
+        version: 1,
+        _raw_data_length: 8,
+        pdf_data: common::RawData(b"%PDF-1.3".to_vec()),
+    });
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MySymbol".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let vector = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(vector["AssetType"], "Vector");
+    assert_eq!(vector["Vector Based"], true);
+}
+
+#[test]
+fn cursor_hotspot() {
+    let mut asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &mut asset_storage.theme_store.store;
+    let (_, key_token) = store
+        .facetkeysdb
+        .iter_mut()
+        .find(|(name, _)| name == "MyPNG")
+        .expect("No facet found");
+    assert_eq!(key_token.cursor_hotspot(), (0, 0));
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    assert!(entries.iter().all(|entry| entry.hotspot.is_none()));
+
+    // hotspot (3, 5), 1 attribute: Identifier 32625
+    let [low, high] = 32625u16.to_le_bytes();
+    let (_, key_token) = store
+        .facetkeysdb
+        .iter_mut()
+        .find(|(name, _)| name == "MyPNG")
+        .expect("No facet found");
+    *key_token = coreui::rendition::KeyToken::read_le(&mut Cursor::new(vec![
+        3, 0, 5, 0, 1, 0, 17, 0, low, high,
+    ]))
+    .expect("Unable to parse key token");
+    for entry in assetutil::AssetUtilEntry::entries_from_asset_storage(store) {
+        let entry = serde_json::to_value(entry).expect("Unable to serialize output");
+        if entry["Name"] == "MyPNG" {
+            assert_eq!(entry["Hotspot"], json!({ "x": 3, "y": 5 }));
+        } else {
+            assert!(entry.get("Hotspot").is_none());
+        }
+    }
+}
+
+#[test]
+fn unknown_layout() {
+    let mut csi_header = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Unknown(0x1234);
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("MyFutureAsset".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let unknown = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(unknown["AssetType"], "Unknown");
+    assert_eq!(unknown["LayoutType"], 0x1234);
+
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]),
+        Some("MyPNG".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert!(image.get("LayoutType").is_none());
+}
+
+#[test]
+fn layer_stack() {
+    // links to the layers with Identifier 7, at (0, 0) and (4, 8)
+    let layer_link = |layer: u16, x: u32, y: u32| {
+        let mut data = b"KLNI".to_vec();
+        for value in [1, x, y, 20, 10] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(0x0Cu16.to_le_bytes());
+        data.extend([0, 0, 0, 0, 2, 0, 17, 0, 7, 0, 11, 0]);
+        data.extend(layer.to_le_bytes());
+        coreui::rendition::RenditionLink::read_le(&mut Cursor::new(data))
+            .expect("Unable to parse rendition link")
+    };
+    let mut stack = image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]);
+    stack.csimetadata.layout = coreui::rendition::LayoutType32::LayerStack;
+    stack.rendition_data = Some(coreui::rendition::Rendition::LayerStack {
+        layers: vec![layer_link(1, 0, 0), layer_link(2, 4, 8)],
+    });
+
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &stack,
+        Some("MyStack".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert_eq!(image["AssetType"], "Image");
+    assert_eq!(
+        image["Layers"],
+        json!([
+            {
+                "Frame": {
+                    "origin": { "x": 0.0, "y": 0.0 },
+                    "size": { "width": 20.0, "height": 10.0 },
+                },
+                "NameIdentifier": 7,
+            },
+            {
+                "Frame": {
+                    "origin": { "x": 4.0, "y": 8.0 },
+                    "size": { "width": 20.0, "height": 10.0 },
+                },
+                "NameIdentifier": 7,
+            },
+        ])
+    );
+
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &image_csi_header(coreui::rendition::CompressionType::LZFSE, &[]),
+        Some("MyImage".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let image = serde_json::to_value(entry).expect("Unable to serialize output");
+    assert!(image.get("Layers").is_none());
+}
@@ -0,0 +1,2959 @@
+use binrw::BinRead;
+use binrw::BinWrite;
+use carutil_lib::common;
+use carutil_lib::coregraphics;
+use carutil_lib::coreui;
+use carutil_lib::error;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::io::Write;
+
+// test file from https://blog.timac.org/2018/1018-reverse-engineering-the-car-file-format/
+static CAR_PATH: &str = "./tests/Assets.car";
+// Assets.car with the text of MyText changed from blog.timac.org to blog.timac.net
+static TAMPERED_CAR_PATH: &str = "./tests/Tampered.car";
+// Assets.car with the catalog structures byte swapped, see big_endian_catalog
+static BIG_ENDIAN_CAR_PATH: &str = "./tests/BigEndian.car";
+// Assets.car, 16 bytes of padding then Tampered.car
+static CONCATENATED_CAR_PATH: &str = "./tests/Concatenated.car";
+
+// fixtures are parsed through from_reader, see from_reader for its parity with from
+fn read_car(path: &str) -> coreui::CarUtilAssetStorage {
+    let file = std::fs::File::open(path).expect("Unable to open fixture");
+    coreui::CarUtilAssetStorage::from_reader(file).expect("Unable to parse fixture")
+}
+
+// rendition of a fixture by the name in its CSI header, e.g. "Timac@3x.png"
+fn rendition_named<'a>(
+    store: &'a coreui::CommonAssetStorage,
+    name: &str,
+) -> &'a coreui::csi::Header {
+    store
+        .imagedb
+        .values()
+        .find(|csi_header| csi_header.csimetadata.name() == name)
+        .expect("No rendition found")
+}
+
+fn data_csi_header(payload: &[u8]) -> coreui::csi::Header {
+    coreui::csi::Header {
+        version: 1,
+        rendition_flags: coreui::csi::RenditionFlags(0),
+        width: 0,
+        height: 0,
+        scale_factor: 100,
+        pixel_format: coreui::csi::PixelFormat::Data,
+        color_space: coreui::csi::ColorModel(0),
+        csimetadata: coreui::csi::Metadata {
+            mod_time: 0,
+            layout: coreui::rendition::LayoutType32::Data,
+            name: common::str_to_sized_slice128("CoreStructuredImage"),
+        },
+        csibitmaplist: coreui::csi::BitmapList {
+            tlv_length: 0,
+            bitmap_count: 1,
+            zero: 0,
+            rendition_length: payload.len() as u32 + 12,
+        },
+        tlv_data: common::RawData(vec![]),
+        rendition_data: Some(coreui::rendition::Rendition::RawData {
+            version: 0,
+            _raw_data_length: payload.len() as u32,
+            raw_data: common::RawData(payload.to_vec()),
+        }),
+        endian: binrw::Endian::Little,
+    }
+}
+
+// LZFSE compressed bitmap of an Image rendition
+fn bitmap_csi_header(
+    pixel_format: coreui::csi::PixelFormat,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> coreui::csi::Header {
+    let mut csi_header = data_csi_header(b"");
+    csi_header.width = width;
+    csi_header.height = height;
+    csi_header.pixel_format = pixel_format;
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+    let mut raw_data = vec![];
+    lzfse_rust::encode_bytes(pixels, &mut raw_data).expect("Unable to compress");
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+        version: 0,
+        compression_type: coreui::rendition::CompressionType::LZFSE,
+        _raw_data_length: raw_data.len() as u32,
+        raw_data: common::RawData(raw_data),
+    });
+    csi_header
+}
+
+fn color_csi_header(components: &[f64]) -> coreui::csi::Header {
+    let mut csi_header = data_csi_header(b"");
+    csi_header.pixel_format = coreui::csi::PixelFormat::None;
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Color;
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Color {
+        version: 1,
+        flags: coreui::rendition::ColorFlags(0),
+        component_count: components.len() as u32,
+        components: components.to_vec(),
+    });
+    csi_header
+}
+
+fn asset_storage_with_renditions(
+    attribute_types: Vec<coreui::rendition::AttributeType>,
+    renditions: Vec<([u16; 18], coreui::csi::Header)>,
+) -> coreui::CommonAssetStorage {
+    coreui::CommonAssetStorage {
+        header: coreui::CarHeader::new(
+            802,
+            17,
+            0,
+            renditions.len() as u32,
+            "",
+            "",
+            [0; 16],
+            0,
+            5,
+            0,
+            0,
+        ),
+        extended_metadata: coreui::CarExtendedMetadata::new("", "12.0", "ios", ""),
+        renditionkeyfmt: coreui::rendition::KeyFormat::new(attribute_types),
+        rendition_sha_digests: BTreeMap::new(),
+        rendition_blocks: BTreeMap::new(),
+        imagedb: renditions
+            .into_iter()
+            .map(|(raw, csi_header)| (coreui::rendition::Key { raw }, csi_header))
+            .collect(),
+        facetkeysdb: vec![],
+        bitmapkeydb: None,
+        appearancedb: None,
+        name_index: coreui::NameIndex::default(),
+    }
+}
+
+fn facet_key_token(identifier: u16) -> coreui::rendition::KeyToken {
+    let [low, high] = identifier.to_le_bytes();
+    // hotspot (0, 0), 1 attribute: Identifier
+    coreui::rendition::KeyToken::read_le(&mut Cursor::new(vec![0, 0, 0, 0, 1, 0, 17, 0, low, high]))
+        .expect("Unable to parse key token")
+}
+
+// INLK link to the rendition with this Identifier and Layer
+fn rendition_link(
+    identifier: u16,
+    layer: u16,
+    frame: [u32; 4],
+    layout: coreui::rendition::LayoutType32,
+) -> coreui::rendition::RenditionLink {
+    let mut data = b"KLNI".to_vec();
+    for value in [1, frame[0], frame[1], frame[2], frame[3]] {
+        data.extend(value.to_le_bytes());
+    }
+    data.extend((layout.to_u32() as u16).to_le_bytes());
+    // hotspot (0, 0), 2 attributes
+    data.extend([0, 0, 0, 0, 2, 0]);
+    for (attribute, value) in [(17u16, identifier), (11, layer)] {
+        data.extend(attribute.to_le_bytes());
+        data.extend(value.to_le_bytes());
+    }
+    coreui::rendition::RenditionLink::read_le(&mut Cursor::new(data))
+        .expect("Unable to parse rendition link")
+}
+
+// copy of the fixture with the schema version of its CARHEADER replaced
+fn car_with_schema_version(schema_version: u32) -> Vec<u8> {
+    let mut data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let header = data
+        .windows(4)
+        .position(|window| window == b"RATC")
+        .expect("No CARHEADER found");
+    // magic, four u32 fields, version strings, uuid and checksum come first
+    let offset = header + 4 * 5 + 128 + 256 + 16 + 4;
+    data[offset..offset + 4].copy_from_slice(&schema_version.to_le_bytes());
+    data
+}
+
+#[test]
+fn decoded_footprint() {
+    let asset_storage = read_car(CAR_PATH);
+
+    // MyPNG at 28x28, 56x56, 84x84 plus MyJPG at 200x200, 4 bytes per pixel
+    let expected = (28 * 28 + 56 * 56 + 84 * 84 + 200 * 200) * 4;
+    assert_eq!(
+        asset_storage.theme_store.store.decoded_footprint(),
+        expected
+    );
+}
+
+#[test]
+fn embedded_image_format() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    // MyPDF is stored as a Data rendition
+    let pdf = rendition_named(store, "CoreStructuredImage");
+    assert_eq!(
+        pdf.embedded_image_format(),
+        Some(coreui::csi::EmbeddedImageFormat::PDF)
+    );
+
+    let text = rendition_named(store, "text.txt");
+    assert_eq!(text.embedded_image_format(), None);
+
+    let png = data_csi_header(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+    assert_eq!(
+        png.embedded_image_format(),
+        Some(coreui::csi::EmbeddedImageFormat::PNG)
+    );
+}
+
+#[test]
+fn deployment_targets() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(
+        store.deployment_targets(),
+        vec![format!(
+            "{}{}",
+            store.deployment_platform(),
+            store.deployment_platform_version()
+        )]
+    );
+
+    let mut key1 = [0; 18];
+    key1[0] = 4;
+    key1[1] = 1;
+    let mut key2 = [0; 18];
+    key2[0] = 6;
+    key2[1] = 1;
+    let asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::DeploymentTarget,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![(key1, data_csi_header(b"")), (key2, data_csi_header(b""))],
+    );
+    assert_eq!(
+        asset_storage.deployment_targets(),
+        vec!["ios13.0", "ios15.0"]
+    );
+
+    let mut asset_storage = asset_storage;
+    asset_storage.extended_metadata = coreui::CarExtendedMetadata::new("", "10.15", "macosx", "");
+    assert_eq!(
+        asset_storage.deployment_targets(),
+        vec!["macosx10.15", "macosx12.0"]
+    );
+}
+
+#[test]
+fn key_token_attribute_order() {
+    // hotspot (0, 0), 2 attributes: Element=85, Identifier=32625
+    let key_token_a = coreui::rendition::KeyToken::read_le(&mut Cursor::new(vec![
+        0, 0, 0, 0, 2, 0, 1, 0, 85, 0, 17, 0, 0x71, 0x7f,
+    ]))
+    .expect("Unable to parse key token");
+    // same attributes stored in reverse order
+    let key_token_b = coreui::rendition::KeyToken::read_le(&mut Cursor::new(vec![
+        0, 0, 0, 0, 2, 0, 17, 0, 0x71, 0x7f, 1, 0, 85, 0,
+    ]))
+    .expect("Unable to parse key token");
+    assert_eq!(key_token_a, key_token_b);
+
+    let key_tokens: HashSet<coreui::rendition::KeyToken> =
+        vec![key_token_a, key_token_b].into_iter().collect();
+    assert_eq!(key_tokens.len(), 1);
+}
+
+#[test]
+fn recompress() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac@3x.png");
+
+    let uncompressed = csi_header
+        .recompress(coreui::rendition::CompressionType::Uncompressed)
+        .expect("Unable to recompress");
+    assert_eq!(uncompressed.size, 84 * 84 * 4);
+    assert_eq!(uncompressed.size_on_disk, 1961);
+
+    let lzfse = csi_header
+        .recompress(coreui::rendition::CompressionType::LZFSE)
+        .expect("Unable to recompress");
+    assert!(lzfse.size > 0);
+    assert!(lzfse.size < uncompressed.size);
+
+    let zip = csi_header
+        .recompress(coreui::rendition::CompressionType::ZIP)
+        .expect("Unable to recompress");
+    assert!(zip.size > 0);
+    assert!(zip.size < uncompressed.size);
+
+    // the ZIP payload decodes to the same bitmap
+    let raw_data = csi_header
+        .recompressed_payload(coreui::rendition::CompressionType::ZIP)
+        .expect("Unable to recompress");
+    assert_eq!(raw_data.len(), zip.size);
+    let mut zip_csi_header = csi_header.clone();
+    zip_csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+        version: 0,
+        compression_type: coreui::rendition::CompressionType::ZIP,
+        _raw_data_length: raw_data.len() as u32,
+        raw_data: common::RawData(raw_data),
+    });
+    assert_eq!(
+        zip_csi_header
+            .decoded_pixels()
+            .expect("Unable to decode ZIP"),
+        csi_header.decoded_pixels().expect("Unable to decode LZFSE")
+    );
+}
+
+#[test]
+fn car_header_big_endian() {
+    let car_header = coreui::CarHeader::new(
+        498,
+        15,
+        1539543253,
+        7,
+        "@(#)PROGRAM:CoreUI  PROJECT:CoreUI-498.40.1\n",
+        "IBCocoaTouchImageCatalogTool-10.0",
+        [0; 16],
+        0,
+        2,
+        1,
+        2,
+    );
+
+    let mut little_endian = Cursor::new(Vec::<u8>::new());
+    car_header
+        .write_le(&mut little_endian)
+        .expect("Unable to write header");
+    let mut big_endian = Cursor::new(Vec::<u8>::new());
+    car_header
+        .write_be(&mut big_endian)
+        .expect("Unable to write header");
+    assert_eq!(&little_endian.get_ref()[..4], b"RATC");
+    assert_eq!(&big_endian.get_ref()[..4], b"CTAR");
+
+    little_endian.set_position(0);
+    big_endian.set_position(0);
+    let from_little_endian = coreui::CarHeader::read_with_detected_endian(&mut little_endian)
+        .expect("Unable to parse header");
+    let from_big_endian = coreui::CarHeader::read_with_detected_endian(&mut big_endian)
+        .expect("Unable to parse header");
+    assert_eq!(from_big_endian.storage_timestamp, 1539543253);
+    assert_eq!(
+        format!("{:?}", from_little_endian),
+        format!("{:?}", from_big_endian)
+    );
+}
+
+#[test]
+fn facet_attributes() {
+    let asset_storage = read_car(CAR_PATH);
+    let attributes = asset_storage
+        .theme_store
+        .store
+        .facet_attributes("MyPNG")
+        .expect("No facet found");
+    assert_eq!(
+        attributes,
+        vec![
+            (coreui::rendition::AttributeType::Element, 85),
+            (coreui::rendition::AttributeType::Part, 181),
+            (coreui::rendition::AttributeType::Identifier, 32625),
+        ]
+    );
+    assert!(asset_storage
+        .theme_store
+        .store
+        .facet_attributes("MyMissingAsset")
+        .is_none());
+}
+
+#[test]
+fn validate_duplicate_facet_name() {
+    let asset_storage = read_car(CAR_PATH);
+    assert!(asset_storage.theme_store.store.validate().is_empty());
+
+    let mut asset_storage = asset_storage_with_renditions(vec![], vec![]);
+    asset_storage.facetkeysdb = vec![
+        ("MyPNG".to_string(), facet_key_token(32625)),
+        ("MyPNG".to_string(), facet_key_token(32626)),
+        ("MyJPG".to_string(), facet_key_token(48301)),
+    ];
+    assert_eq!(
+        asset_storage.validate(),
+        vec![coreui::ValidationIssue::DuplicateFacetName {
+            name: "MyPNG".to_string(),
+            identifiers: vec![32625, 32626],
+        }]
+    );
+}
+
+#[test]
+fn tlv_bytes() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac@3x.png");
+    assert_eq!(
+        csi_header.tlv_bytes().len(),
+        csi_header.csibitmaplist.tlv_length as usize
+    );
+    assert_eq!(csi_header.tlv_bytes().len(), 104);
+}
+
+#[test]
+fn key_format_newer_version() {
+    let mut data = b"tmfk".to_vec();
+    data.extend_from_slice(&1u32.to_le_bytes()); // version
+    data.extend_from_slice(&4u32.to_le_bytes()); // max_count
+    for attribute_type in [7u32, 17, 26, 27] {
+        data.extend_from_slice(&attribute_type.to_le_bytes());
+    }
+
+    let key_format = coreui::rendition::KeyFormat::read_le(&mut Cursor::new(data))
+        .expect("Unable to parse key format");
+    assert_eq!(key_format.version, 1);
+    assert_eq!(
+        key_format.attribute_types,
+        vec![
+            coreui::rendition::AttributeType::Appearance,
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::GlyphWeight,
+            coreui::rendition::AttributeType::GlyphSize,
+        ]
+    );
+}
+
+#[test]
+fn decode_premultiplied_alpha() {
+    // one half transparent pixel, stored premultiplied as BGRA
+    let csi_header = bitmap_csi_header(coreui::csi::PixelFormat::ARGB, 1, 1, &[40, 60, 100, 128]);
+
+    let decode = |premultiply_alpha| {
+        csi_header
+            .decoded_pixels_with_options(&coreui::csi::DecodeOptions {
+                channel_order: coreui::csi::ChannelOrder::RGBA,
+                premultiply_alpha,
+            })
+            .expect("Unable to decode pixels")
+    };
+    assert_eq!(decode(true), vec![100, 60, 40, 128]);
+    assert_eq!(
+        decode(true),
+        csi_header.decoded_pixels().expect("Unable to decode")
+    );
+    assert_eq!(decode(false), vec![199, 120, 80, 128]);
+}
+
+#[test]
+fn decode_wide_pixel_formats() {
+    let decode = |pixel_format, components: &[u16]| {
+        let pixels: Vec<u8> = components
+            .iter()
+            .flat_map(|component| component.to_le_bytes())
+            .collect();
+        bitmap_csi_header(pixel_format, 1, 1, &pixels)
+            .decoded_pixels()
+            .expect("Unable to decode pixels")
+    };
+
+    // BGRA components
+    assert_eq!(
+        decode(
+            coreui::csi::PixelFormat::ARGB16,
+            &[0xFFFF, 0x8000, 0, 0xFFFF]
+        ),
+        vec![0, 128, 255, 255]
+    );
+    // gray and alpha
+    assert_eq!(
+        decode(coreui::csi::PixelFormat::Gray16, &[0x4000, 0xFFFF]),
+        vec![64, 64, 64, 255]
+    );
+    // 1.0, 0.5, 2.0 and 1.0 as half floats, 2.0 is clamped
+    assert_eq!(
+        decode(
+            coreui::csi::PixelFormat::ARGBHalf,
+            &[0x3C00, 0x3800, 0x4000, 0x3C00]
+        ),
+        vec![255, 128, 255, 255]
+    );
+
+    // 8 bytes a pixel are needed
+    let csi_header = bitmap_csi_header(coreui::csi::PixelFormat::ARGB16, 1, 1, &[0; 4]);
+    let err = csi_header
+        .decoded_pixels()
+        .expect_err("Decoded a truncated bitmap");
+    assert!(matches!(
+        err.downcast_ref::<error::CarError>(),
+        Some(error::CarError::SizeMismatch { .. })
+    ));
+}
+
+#[test]
+fn decode_bgra() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac@3x.png");
+
+    let rgba = csi_header
+        .decoded_pixels()
+        .expect("Unable to decode pixels");
+    let bgra = csi_header
+        .decoded_pixels_with_options(&coreui::csi::DecodeOptions {
+            channel_order: coreui::csi::ChannelOrder::BGRA,
+            premultiply_alpha: true,
+        })
+        .expect("Unable to decode pixels");
+    assert_eq!(rgba.len(), bgra.len());
+
+    // pick a pixel where red and blue differ so the swap is observable
+    let index = rgba
+        .chunks_exact(4)
+        .position(|pixel| pixel[0] != pixel[2])
+        .expect("No suitable pixel found")
+        * 4;
+    assert_eq!(
+        &bgra[index..index + 4],
+        &[
+            rgba[index + 2],
+            rgba[index + 1],
+            rgba[index],
+            rgba[index + 3]
+        ]
+    );
+}
+
+#[test]
+fn renditions_by_layout() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let images = store.renditions_by_layout(coreui::rendition::LayoutType32::Image);
+    let mut names: Vec<String> = images
+        .iter()
+        .map(|csi_header| csi_header.csimetadata.name())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["Timac.png", "Timac@2x.png", "Timac@3x.png", "TimacJPG.jpg"]
+    );
+
+    assert_eq!(
+        store
+            .renditions_by_layout(coreui::rendition::LayoutType32::Data)
+            .len(),
+        2
+    );
+    assert_eq!(
+        store
+            .renditions_by_layout(coreui::rendition::LayoutType32::Color)
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn resolve_symbol() {
+    // star's content links to its layer reference, which places the glyph
+    // drawn by another rendition
+    let mut content = data_csi_header(b"");
+    content.csimetadata.layout = coreui::rendition::LayoutType32::ContentRendition;
+    content.rendition_data = Some(coreui::rendition::Rendition::InternalLink(rendition_link(
+        1,
+        1,
+        [0, 0, 0, 0],
+        coreui::rendition::LayoutType32::LayerReference,
+    )));
+    let mut layer = data_csi_header(b"");
+    layer.csimetadata.layout = coreui::rendition::LayoutType32::LayerReference;
+    layer.rendition_data = Some(coreui::rendition::Rendition::InternalLink(rendition_link(
+        3,
+        0,
+        [2, 4, 16, 16],
+        coreui::rendition::LayoutType32::Vector,
+    )));
+    let mut glyph = data_csi_header(b"%PDF");
+    glyph.csimetadata.layout = coreui::rendition::LayoutType32::Vector;
+    let mut other = data_csi_header(b"");
+    other.csimetadata.layout = coreui::rendition::LayoutType32::Data;
+    // links back to itself
+    let mut looped = data_csi_header(b"");
+    looped.csimetadata.layout = coreui::rendition::LayoutType32::LayerReference;
+    looped.rendition_data = Some(coreui::rendition::Rendition::InternalLink(rendition_link(
+        4,
+        0,
+        [0, 0, 1, 1],
+        coreui::rendition::LayoutType32::LayerReference,
+    )));
+
+    let key = |identifier, layer| {
+        let mut raw = [0; 18];
+        raw[0] = identifier;
+        raw[1] = layer;
+        raw
+    };
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::Layer,
+        ],
+        vec![
+            (key(1, 0), content),
+            (key(1, 1), layer),
+            (key(2, 0), other),
+            (key(3, 0), glyph),
+            (key(4, 0), looped),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![
+        ("star".to_string(), facet_key_token(1)),
+        ("notes".to_string(), facet_key_token(2)),
+        ("loop".to_string(), facet_key_token(4)),
+    ];
+
+    let composition = asset_storage
+        .resolve_symbol("star")
+        .expect("No symbol found");
+    assert_eq!(composition.name, "star");
+    let layouts: Vec<coreui::rendition::LayoutType32> = composition
+        .layers
+        .iter()
+        .map(|layer| layer.layout)
+        .collect();
+    assert_eq!(
+        layouts,
+        vec![
+            coreui::rendition::LayoutType32::ContentRendition,
+            coreui::rendition::LayoutType32::LayerReference,
+        ]
+    );
+    let references: Vec<Vec<[u16; 18]>> = composition
+        .layers
+        .iter()
+        .map(|layer| layer.references.iter().map(|key| key.raw).collect())
+        .collect();
+    assert_eq!(
+        references,
+        vec![vec![key(1, 1), key(3, 0)], vec![key(3, 0)]]
+    );
+    assert_eq!(
+        composition.layers[1].frame,
+        Some(coreui::SpriteRect {
+            x: 2,
+            y: 4,
+            width: 16,
+            height: 16,
+        })
+    );
+    assert!(asset_storage.resolve_symbol("notes").is_none());
+
+    let composition = asset_storage
+        .resolve_symbol("loop")
+        .expect("No symbol found");
+    assert!(composition.layers[0].references.is_empty());
+}
+
+#[test]
+fn layer_stack_layers() {
+    let named = |name: &str, layout| {
+        let mut csi_header = data_csi_header(b"");
+        csi_header.csimetadata.layout = layout;
+        csi_header.csimetadata.name = common::str_to_sized_slice128(name);
+        csi_header
+    };
+    let key = |identifier, layer| {
+        let mut raw = [0; 18];
+        raw[0] = identifier;
+        raw[1] = layer;
+        raw
+    };
+    let layer_link = |identifier, layer| {
+        rendition_link(
+            identifier,
+            layer,
+            [0, 0, 10, 10],
+            coreui::rendition::LayoutType32::Image,
+        )
+    };
+
+    // two layers then a link to a missing rendition, in drawing order
+    let mut layers = Cursor::new(Vec::<u8>::new());
+    for link in [layer_link(1, 2), layer_link(1, 1), layer_link(3, 1)] {
+        link.write_le(&mut layers).expect("Unable to write link");
+    }
+    let layers = layers.into_inner();
+    let mut stack = named("Stack", coreui::rendition::LayoutType32::LayerStack);
+    stack.csibitmaplist.rendition_length = layers.len() as u32;
+    stack.rendition_data = None;
+    let mut data = Cursor::new(Vec::<u8>::new());
+    stack.write_le(&mut data).expect("Unable to write header");
+    data.write_all(&layers).expect("Unable to write payload");
+    // payloads that aren't links are left out
+    stack.csibitmaplist.rendition_length = 4;
+    stack.write_le(&mut data).expect("Unable to write header");
+    data.write_all(b"KLNI").expect("Unable to write payload");
+    data_csi_header(b"hello")
+        .write_le(&mut data)
+        .expect("Unable to write header");
+
+    data.set_position(0);
+    let stack = coreui::csi::Header::read_le(&mut data).expect("Unable to parse layer stack");
+    let identifiers: Vec<Option<u16>> = stack
+        .layer_stack_layers()
+        .expect("No layers found")
+        .iter()
+        .map(|layer| layer.identifier())
+        .collect();
+    assert_eq!(identifiers, vec![Some(1), Some(1), Some(3)]);
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse layer stack");
+    assert!(csi_header.layer_stack_layers().is_none());
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse data");
+    assert_eq!(csi_header.raw_bytes(), Some(&b"hello"[..]));
+
+    let asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::Layer,
+        ],
+        vec![
+            (key(1, 0), stack),
+            (
+                key(1, 2),
+                named("Front", coreui::rendition::LayoutType32::Image),
+            ),
+            (
+                key(1, 1),
+                named("Back", coreui::rendition::LayoutType32::Image),
+            ),
+            (
+                key(2, 1),
+                named("Other", coreui::rendition::LayoutType32::Image),
+            ),
+        ],
+    );
+
+    let layers: Vec<String> = asset_storage
+        .layer_stack_layers(&coreui::rendition::Key { raw: key(1, 0) })
+        .into_iter()
+        .map(|(_, csi_header)| csi_header.csimetadata.name())
+        .collect();
+    assert_eq!(layers, vec!["Front", "Back"]);
+    assert!(asset_storage
+        .layer_stack_layers(&coreui::rendition::Key { raw: key(1, 2) })
+        .is_empty());
+}
+
+#[test]
+fn pixel_hash() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let csi_header = rendition_named(store, "Timac@3x.png");
+    let pixel_hash = csi_header.pixel_hash().expect("Unable to hash pixels");
+    assert_eq!(
+        csi_header.pixel_hash().expect("Unable to hash pixels"),
+        pixel_hash
+    );
+    assert_ne!(
+        rendition_named(store, "Timac@2x.png")
+            .pixel_hash()
+            .expect("Unable to hash pixels"),
+        pixel_hash
+    );
+}
+
+#[test]
+fn car_header_bad_magic() {
+    let car_header = coreui::CarHeader::new(498, 15, 0, 0, "", "", [0; 16], 0, 2, 0, 0);
+    let mut data = Cursor::new(Vec::<u8>::new());
+    car_header
+        .write_le(&mut data)
+        .expect("Unable to write header");
+
+    data.set_position(0);
+    assert!(coreui::CarHeader::read_with_detected_endian(&mut data).is_ok());
+
+    data.get_mut()[..4].copy_from_slice(b"XATC");
+    data.set_position(0);
+    match coreui::CarHeader::read_with_detected_endian(&mut data) {
+        Err(binrw::Error::BadMagic { pos, found }) => {
+            assert_eq!(pos, 0);
+            assert_eq!(format!("{:?}", found), format!("{:?}", b"XATC"));
+        }
+        result => panic!("Expected BadMagic error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn available_scales() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let facet = store.facet("MyPNG").expect("No facet found");
+    assert_eq!(facet.renditions.len(), 3);
+    assert_eq!(facet.available_scales(), vec![1, 2, 3]);
+
+    let facet = store.facet("MyJPG").expect("No facet found");
+    assert_eq!(facet.available_scales(), vec![1]);
+
+    assert_eq!(store.facets().len(), 5);
+}
+
+#[test]
+fn metadata_name_boundary() {
+    let name = "a".repeat(127);
+    let metadata = coreui::csi::Metadata {
+        mod_time: 0,
+        layout: coreui::rendition::LayoutType32::Image,
+        name: common::str_to_sized_slice128(&name),
+    };
+    assert_eq!(metadata.name(), name);
+    assert!(!metadata.is_name_truncated());
+
+    // no room left for the NUL terminator
+    let name = "b".repeat(200);
+    let metadata = coreui::csi::Metadata {
+        mod_time: 0,
+        layout: coreui::rendition::LayoutType32::Image,
+        name: common::str_to_sized_slice128(&name),
+    };
+    assert_eq!(metadata.name(), "b".repeat(128));
+    assert!(metadata.is_name_truncated());
+
+    let mut raw_name = [0u8; 128];
+    raw_name[..4].copy_from_slice(&[b'a', 0xff, b'b', 0]);
+    let metadata = coreui::csi::Metadata {
+        mod_time: 0,
+        layout: coreui::rendition::LayoutType32::Image,
+        name: raw_name,
+    };
+    assert_eq!(metadata.name(), "a\u{FFFD}b");
+}
+
+#[test]
+fn compression_ratio() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac@3x.png");
+
+    let compression_ratio = csi_header
+        .compression_ratio()
+        .expect("No compression ratio");
+    let expected = 1961.0 / (84.0 * 84.0 * 4.0);
+    assert!((compression_ratio - expected).abs() < 0.0001);
+}
+
+#[test]
+fn raw_header() {
+    let asset_storage = read_car(CAR_PATH);
+
+    let car_header = asset_storage.raw_header();
+    assert_eq!(car_header.magic, 0x43544152);
+    assert_eq!(car_header.rendition_count, 7);
+    assert_eq!(car_header.associated_checksum, 2039897368);
+    assert_eq!(car_header.color_space_id, 1);
+    assert_eq!(car_header.key_semantics, 2);
+
+    let extended_metadata = asset_storage.raw_extended_metadata();
+    assert_eq!(extended_metadata.magic, 0x4154454D);
+    assert_eq!(
+        common::parse_padded_string(&extended_metadata.deployment_platform),
+        "ios"
+    );
+}
+
+#[test]
+fn colors_by_appearance() {
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Appearance,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            (
+                [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[1.0, 1.0, 1.0, 1.0]),
+            ),
+            (
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[0.0, 0.0, 0.0, 1.0]),
+            ),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![("MyDynamicColor".to_string(), facet_key_token(1))];
+    asset_storage.appearancedb = Some(BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ]));
+
+    let colors = asset_storage
+        .facet("MyDynamicColor")
+        .expect("No facet found")
+        .colors_by_appearance();
+    assert_eq!(colors.len(), 2);
+    assert_eq!(colors["UIAppearanceAny"], [1.0, 1.0, 1.0, 1.0]);
+    assert_eq!(colors["UIAppearanceDark"], [0.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn bitmap_count() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac@3x.png");
+
+    // a single bitmap of rendition_length bytes follows the TLVs
+    assert_eq!(csi_header.csibitmaplist.bitmap_count, 1);
+    assert_eq!(csi_header.csibitmaplist.rendition_length, 1673);
+}
+
+#[test]
+fn extract_nested_name() {
+    let output_dir = std::env::temp_dir().join(format!("carutil-extract-{}", std::process::id()));
+    let output_dir_str = output_dir.to_str().expect("Invalid temp dir");
+
+    let mut csi_header = data_csi_header(b"\x89PNG\r\n\x1a\n");
+    csi_header.csimetadata.name = common::str_to_sized_slice128("Folder/MyPNG.png");
+    let output_path = csi_header
+        .extract(output_dir_str)
+        .expect("Unable to extract")
+        .expect("Nothing extracted");
+    assert_eq!(
+        output_path,
+        output_dir
+            .join("Folder")
+            .join("MyPNG.png")
+            .to_str()
+            .unwrap()
+    );
+    assert!(output_dir.join("Folder/MyPNG.png").is_file());
+
+    csi_header.csimetadata.name = common::str_to_sized_slice128("../MyPNG.png");
+    assert!(csi_header.extract(output_dir_str).is_err());
+    csi_header.csimetadata.name = common::str_to_sized_slice128("/tmp/MyPNG.png");
+    assert!(csi_header.extract(output_dir_str).is_err());
+
+    std::fs::remove_dir_all(&output_dir).expect("Unable to clean up");
+}
+
+#[test]
+fn layout_histogram() {
+    let asset_storage = read_car(CAR_PATH);
+    let histogram = asset_storage.theme_store.store.layout_histogram();
+    assert_eq!(
+        histogram,
+        HashMap::from([
+            (coreui::rendition::LayoutType32::Image, 4),
+            (coreui::rendition::LayoutType32::Data, 2),
+            (coreui::rendition::LayoutType32::Color, 1),
+        ])
+    );
+}
+
+#[test]
+fn preserved_vector_pdf() {
+    let pdf = b"%PDF-1.3\n%%EOF\n";
+    let mut tlv_data = vec![];
+    tlv_data.extend_from_slice(&0x3F2u32.to_le_bytes());
+    tlv_data.extend_from_slice(&(pdf.len() as u32).to_le_bytes());
+    tlv_data.extend_from_slice(pdf);
+
+    let mut raster = vec![];
+    lzfse_rust::encode_bytes(&[0xffu8; 4 * 4 * 4], &mut raster).expect("Unable to compress");
+
+    let mut csi_header = data_csi_header(b"");
+    csi_header.width = 4;
+    csi_header.height = 4;
+    csi_header.pixel_format = coreui::csi::PixelFormat::ARGB;
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+        version: 0,
+        compression_type: coreui::rendition::CompressionType::LZFSE,
+        _raw_data_length: raster.len() as u32,
+        raw_data: common::RawData(raster),
+    });
+
+    assert_eq!(csi_header.preserved_vector_pdf(), Some(pdf.to_vec()));
+
+    let png_csi_header = data_csi_header(b"\x89PNG\r\n\x1a\n");
+    assert_eq!(png_csi_header.preserved_vector_pdf(), None);
+}
+
+#[test]
+fn tlv_tag_histogram() {
+    let asset_storage = read_car(CAR_PATH);
+    let histogram = asset_storage.theme_store.store.tlv_tag_histogram();
+    assert_eq!(
+        histogram,
+        HashMap::from([
+            (0x3E9, 4),
+            (0x3EB, 4),
+            (0x3EC, 7),
+            (0x3ED, 1),
+            (0x3EE, 7),
+            (0x3EF, 3),
+        ])
+    );
+}
+
+#[test]
+fn recognition_object() {
+    let uti = b"com.apple.arobject\0\0";
+    let mut tlv_data = vec![];
+    tlv_data.extend_from_slice(&0x3EDu32.to_le_bytes());
+    tlv_data.extend_from_slice(&(uti.len() as u32 + 8).to_le_bytes());
+    tlv_data.extend_from_slice(&(uti.len() as u32).to_le_bytes());
+    tlv_data.extend_from_slice(&0u32.to_le_bytes());
+    tlv_data.extend_from_slice(uti);
+
+    let mut csi_header = data_csi_header(b"arobject payload");
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::RecognitionObject;
+    csi_header.csimetadata.name = common::str_to_sized_slice128("MyObject.arobject");
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+
+    let recognition_object = csi_header
+        .recognition_object()
+        .expect("No recognition object found");
+    assert_eq!(recognition_object.name, "MyObject.arobject");
+    assert_eq!(
+        recognition_object.object_type.as_deref(),
+        Some("com.apple.arobject")
+    );
+    assert_eq!(recognition_object.data, b"arobject payload");
+
+    assert!(data_csi_header(b"").recognition_object().is_none());
+}
+
+#[test]
+fn export_sprite_sheet() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let (sprite_sheet, sprite_map) = store
+        .export_sprite_sheet(&coreui::SpriteSheetOptions::default())
+        .expect("Unable to export sprite sheet");
+
+    // MyJPG isn't decoded, colors, PDF and text aren't images
+    assert_eq!(sprite_map.keys().collect::<Vec<&String>>(), vec!["MyPNG"]);
+    assert_eq!(
+        sprite_map["MyPNG"],
+        coreui::SpriteRect {
+            x: 0,
+            y: 0,
+            width: 28,
+            height: 28,
+        }
+    );
+    assert_eq!((sprite_sheet.width, sprite_sheet.height), (28, 28));
+
+    let csi_header = rendition_named(store, "Timac.png");
+    assert_eq!(
+        sprite_sheet.pixels,
+        csi_header.decoded_pixels().expect("Unable to decode")
+    );
+
+    let (sprite_sheet, _) = store
+        .export_sprite_sheet(&coreui::SpriteSheetOptions {
+            scale: 5,
+            ..Default::default()
+        })
+        .expect("Unable to export sprite sheet");
+    assert_eq!((sprite_sheet.width, sprite_sheet.height), (84, 84));
+}
+
+#[test]
+fn sprite_sheet_pack() {
+    let sprite = |name: &str, width: u32, height: u32, value: u8| coreui::Sprite {
+        name: name.to_string(),
+        width,
+        height,
+        pixels: vec![value; (width * height * 4) as usize],
+    };
+    let options = coreui::SpriteSheetOptions {
+        scale: 1,
+        padding: 1,
+        max_width: 5,
+    };
+    let (sprite_sheet, sprite_map) = coreui::SpriteSheet::pack(
+        vec![
+            sprite("A", 2, 2, 1),
+            sprite("B", 3, 1, 2),
+            sprite("C", 2, 3, 3),
+        ],
+        &options,
+    )
+    .expect("Unable to pack sprites");
+
+    assert_eq!(
+        sprite_map["C"],
+        coreui::SpriteRect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 3
+        }
+    );
+    assert_eq!(
+        sprite_map["A"],
+        coreui::SpriteRect {
+            x: 3,
+            y: 0,
+            width: 2,
+            height: 2
+        }
+    );
+    assert_eq!(
+        sprite_map["B"],
+        coreui::SpriteRect {
+            x: 0,
+            y: 4,
+            width: 3,
+            height: 1
+        }
+    );
+    assert_eq!((sprite_sheet.width, sprite_sheet.height), (5, 5));
+    assert_eq!(sprite_sheet.pixels[(4 * 5 + 2) * 4], 2);
+    assert_eq!(sprite_sheet.pixels[(3 * 5 + 2) * 4], 0);
+
+    // the size of the sheet overflows instead of allocating
+    let huge = coreui::Sprite {
+        name: "D".to_string(),
+        width: u32::MAX,
+        height: u32::MAX,
+        pixels: vec![],
+    };
+    let options = coreui::SpriteSheetOptions {
+        scale: 1,
+        padding: 0,
+        max_width: u32::MAX,
+    };
+    assert!(matches!(
+        coreui::SpriteSheet::pack(vec![huge], &options),
+        Err(error::CarError::Parse(_))
+    ));
+}
+
+#[test]
+fn decode_size_mismatch() {
+    let asset_storage = read_car(CAR_PATH);
+    let mut csi_header = rendition_named(&asset_storage.theme_store.store, "Timac.png").clone();
+    csi_header.width = 4096;
+    csi_header.height = 4096;
+
+    let err = csi_header
+        .decoded_pixels()
+        .expect_err("Decoded pixels past the payload");
+    assert!(matches!(
+        err.downcast_ref::<error::CarError>(),
+        Some(error::CarError::SizeMismatch { expected, actual })
+            if *expected == 4096 * 4096 * 4 && actual < expected
+    ));
+}
+
+#[test]
+fn best_rendition() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let csi_header = store
+        .best_rendition("MyPNG", coreui::rendition::Idiom::Universal, 3, None)
+        .expect("No rendition found");
+    assert_eq!(csi_header.csimetadata.name(), "Timac@3x.png");
+
+    // universal renditions are used for other idioms, and Any for other appearances
+    let csi_header = store
+        .best_rendition(
+            "MyPNG",
+            coreui::rendition::Idiom::Phone,
+            2,
+            Some("UIAppearanceDark"),
+        )
+        .expect("No rendition found");
+    assert_eq!(csi_header.csimetadata.name(), "Timac@2x.png");
+
+    assert!(store
+        .best_rendition("MyMissing", coreui::rendition::Idiom::Universal, 1, None)
+        .is_none());
+}
+
+#[test]
+fn best_rendition_appearance() {
+    let mut any = data_csi_header(b"any");
+    any.csimetadata.name = common::str_to_sized_slice128("Any.png");
+    let mut dark = data_csi_header(b"dark");
+    dark.csimetadata.name = common::str_to_sized_slice128("Dark.png");
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Appearance,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            ([0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], any),
+            ([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], dark),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![("MyImage".to_string(), facet_key_token(1))];
+    asset_storage.appearancedb = Some(BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ]));
+
+    let best_name = |appearance| {
+        asset_storage
+            .best_rendition(
+                "MyImage",
+                coreui::rendition::Idiom::Universal,
+                1,
+                appearance,
+            )
+            .map(|csi_header| csi_header.csimetadata.name())
+    };
+    assert_eq!(best_name(None).as_deref(), Some("Any.png"));
+    assert_eq!(
+        best_name(Some("UIAppearanceDark")).as_deref(),
+        Some("Dark.png")
+    );
+    assert_eq!(
+        best_name(Some("UIAppearanceLight")).as_deref(),
+        Some("Any.png")
+    );
+}
+
+#[test]
+fn extended_metadata_space_padded() {
+    let padded = |string: &str| {
+        let mut field = string.as_bytes().to_vec();
+        field.resize(256, b' ');
+        field
+    };
+    let mut data = 0x4154454Du32.to_le_bytes().to_vec();
+    data.extend(padded(""));
+    data.extend(padded("12.0"));
+    data.extend(padded("ios\0\x01\x02"));
+    data.extend(padded("actool-linux"));
+
+    let mut asset_storage = asset_storage_with_renditions(vec![], vec![]);
+    asset_storage.extended_metadata = coreui::CarExtendedMetadata::read_le(&mut Cursor::new(data))
+        .expect("Unable to parse extended metadata");
+
+    assert_eq!(asset_storage.thinning_arguments(), "");
+    assert_eq!(asset_storage.deployment_platform_version(), "12.0");
+    assert_eq!(asset_storage.deployment_platform(), "ios");
+    assert_eq!(asset_storage.authoring_tool(), "actool-linux");
+}
+
+#[test]
+fn car_util_extended_metadata() {
+    let asset_storage = read_car(CAR_PATH);
+    assert_eq!(asset_storage.thinning_arguments(), "");
+    assert_eq!(asset_storage.deployment_platform(), "ios");
+    assert_eq!(asset_storage.deployment_platform_version(), "12.0");
+    assert_eq!(
+        asset_storage.authoring_tool(),
+        "@(#)PROGRAM:CoreThemeDefinition  PROJECT:CoreThemeDefinition-346.29\n"
+    );
+    assert_eq!(asset_storage.associated_checksum(), 0x79965D18);
+}
+
+#[test]
+fn framing_rect() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "Timac.png");
+    let framing_rect = csi_header.framing_rect().expect("No framing rect");
+    assert_eq!((framing_rect.origin.x, framing_rect.origin.y), (0.0, 0.0));
+    assert_eq!(
+        (framing_rect.size.width, framing_rect.size.height),
+        (28.0, 28.0)
+    );
+
+    // 30x40 frame with 2/3 top left and 4/5 bottom right insets
+    let mut tlv_data = vec![];
+    for value in [0x3EB, 28, 1, 2, 3, 4, 5, 40, 30u32] {
+        tlv_data.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut csi_header = data_csi_header(b"");
+    csi_header.csibitmaplist.tlv_length = tlv_data.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv_data);
+    let framing_rect = csi_header.framing_rect().expect("No framing rect");
+    assert_eq!((framing_rect.origin.x, framing_rect.origin.y), (2.0, 3.0));
+    assert_eq!(
+        (framing_rect.size.width, framing_rect.size.height),
+        (24.0, 32.0)
+    );
+
+    assert!(data_csi_header(b"").framing_rect().is_none());
+}
+
+#[test]
+fn mip_levels() {
+    let mip_level = |size: u32, compression_type| {
+        let size_usize = size as usize;
+        let mut csi_header = bitmap_csi_header(
+            coreui::csi::PixelFormat::ARGB,
+            size,
+            size,
+            &[1u8, 2, 3, 4].repeat(size_usize * size_usize),
+        );
+        csi_header.csimetadata.layout = coreui::rendition::LayoutType32::TextureImage;
+        if compression_type == coreui::rendition::CompressionType::ASTC {
+            let mut raw_data = vec![0; 12];
+            lzfse_rust::encode_bytes(&[0xAB; 16], &mut raw_data).expect("Unable to compress");
+            csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+                version: 0,
+                compression_type,
+                _raw_data_length: raw_data.len() as u32,
+                raw_data: common::RawData(raw_data),
+            });
+        }
+        csi_header
+    };
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::Dimension1,
+        ],
+        vec![
+            (
+                [1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                mip_level(1, coreui::rendition::CompressionType::LZFSE),
+            ),
+            (
+                [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                mip_level(4, coreui::rendition::CompressionType::LZFSE),
+            ),
+            (
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                mip_level(2, coreui::rendition::CompressionType::LZFSE),
+            ),
+            (
+                [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                mip_level(4, coreui::rendition::CompressionType::ASTC),
+            ),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![
+        ("MyTexture".to_string(), facet_key_token(1)),
+        ("MyASTCTexture".to_string(), facet_key_token(2)),
+    ];
+
+    let mip_levels = asset_storage
+        .facet("MyTexture")
+        .expect("Unable to find MyTexture")
+        .mip_levels()
+        .expect("Unable to decode mip levels");
+    let sizes: Vec<(u32, u32)> = mip_levels
+        .iter()
+        .map(|mip_level| mip_level.size())
+        .collect();
+    assert_eq!(sizes, vec![(4, 4), (2, 2), (1, 1)]);
+    match &mip_levels[2] {
+        coreui::MipLevel::Decoded(decoded_image) => {
+            assert_eq!(decoded_image.pixels, vec![3, 2, 1, 4])
+        }
+        mip_level => panic!("Unexpected mip level {:?}", mip_level),
+    }
+
+    // ASTC blocks are kept compressed
+    let mip_levels = asset_storage
+        .facet("MyASTCTexture")
+        .expect("Unable to find MyASTCTexture")
+        .mip_levels()
+        .expect("Unable to read ASTC mip levels");
+    match &mip_levels[..] {
+        [coreui::MipLevel::ASTC {
+            width: 4,
+            height: 4,
+            blocks,
+        }] => assert_eq!(blocks, &[0xAB; 16]),
+        mip_levels => panic!("Unexpected mip levels {:?}", mip_levels),
+    }
+}
+
+#[test]
+fn key_format_decode() {
+    let asset_storage = read_car(CAR_PATH);
+    let key_format = &asset_storage.theme_store.store.renditionkeyfmt;
+
+    assert_eq!(key_format.attributes().len(), 18);
+    assert_eq!(
+        key_format.position(coreui::rendition::AttributeType::Scale),
+        Some(1)
+    );
+    assert_eq!(
+        key_format.position(coreui::rendition::AttributeType::Identifier),
+        Some(11)
+    );
+    assert_eq!(
+        key_format.position(coreui::rendition::AttributeType::GlyphSize),
+        None
+    );
+
+    let rendition_key = key_format.decode(&[0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 32625, 85, 181]);
+    assert_eq!(
+        rendition_key.find_attribute(key_format, coreui::rendition::AttributeType::Scale),
+        Some(3)
+    );
+    assert_eq!(
+        rendition_key.find_attribute(key_format, coreui::rendition::AttributeType::Identifier),
+        Some(32625)
+    );
+    assert_eq!(
+        rendition_key.find_attribute(key_format, coreui::rendition::AttributeType::Dimension2),
+        Some(0)
+    );
+    assert!(asset_storage
+        .theme_store
+        .store
+        .imagedb
+        .contains_key(&rendition_key));
+}
+
+#[test]
+fn missing_scales() {
+    let asset_storage = read_car(CAR_PATH);
+    assert_eq!(
+        asset_storage.theme_store.store.missing_scales(&[1, 2, 3]),
+        vec![("MyJPG".to_string(), vec![2, 3])]
+    );
+
+    let image = || {
+        let mut csi_header = data_csi_header(b"");
+        csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+        csi_header
+    };
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Scale,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            (
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image(),
+            ),
+            (
+                [3, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image(),
+            ),
+            (
+                [1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image(),
+            ),
+            (
+                [2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image(),
+            ),
+            (
+                [3, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image(),
+            ),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![
+        ("MyIcon".to_string(), facet_key_token(1)),
+        ("MyImage".to_string(), facet_key_token(2)),
+    ];
+    assert_eq!(
+        asset_storage.missing_scales(&[1, 2, 3]),
+        vec![("MyIcon".to_string(), vec![2])]
+    );
+}
+
+#[test]
+fn build_time() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(
+        store.build_time(),
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1539543253)
+    );
+    assert!(store.age() > std::time::Duration::from_secs(365 * 24 * 60 * 60));
+}
+
+#[test]
+fn multisize_image_set_members() {
+    let mut image_set = data_csi_header(b"");
+    image_set.csimetadata.layout = coreui::rendition::LayoutType32::MultisizeImage;
+    image_set.rendition_data = Some(coreui::rendition::Rendition::MultisizeImageSet {
+        version: 1,
+        sizes_count: 2,
+        entries: vec![
+            coreui::rendition::MultisizeImageSetEntry {
+                width: 20,
+                height: 20,
+                index: 1,
+                idiom: coreui::rendition::Idiom::Phone,
+            },
+            coreui::rendition::MultisizeImageSetEntry {
+                width: 29,
+                height: 29,
+                index: 2,
+                idiom: coreui::rendition::Idiom::Phone,
+            },
+        ],
+    });
+    let image = |name: &str| {
+        let mut csi_header = data_csi_header(b"");
+        csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+        csi_header.csimetadata.name = common::str_to_sized_slice128(name);
+        csi_header
+    };
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Scale,
+            coreui::rendition::AttributeType::Idiom,
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::Dimension1,
+        ],
+        vec![
+            (
+                [0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image_set,
+            ),
+            (
+                [2, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image("Icon20@2x.png"),
+            ),
+            (
+                [1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image("Icon20.png"),
+            ),
+            (
+                [3, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image("Icon29@3x.png"),
+            ),
+            (
+                [2, 2, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                image("Icon29~ipad@2x.png"),
+            ),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![("AppIcon".to_string(), facet_key_token(1))];
+
+    let members: Vec<(u32, u16, u16, String)> = asset_storage
+        .multisize_image_set_members("AppIcon")
+        .iter()
+        .map(|member| {
+            assert_eq!(member.idiom, coreui::rendition::Idiom::Phone);
+            (
+                member.width,
+                member.index,
+                member.scale,
+                member.csi_header.csimetadata.name(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            (20, 1, 1, "Icon20.png".to_string()),
+            (20, 1, 2, "Icon20@2x.png".to_string()),
+            (29, 2, 3, "Icon29@3x.png".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn get_image_data() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let png_data = store
+        .get_image_data("MyPNG", 3, coreui::rendition::Idiom::Universal)
+        .expect("Unable to encode image")
+        .expect("No image found");
+    assert!(png_data.starts_with(b"\x89PNG\r\n\x1a\n"));
+    // IHDR width and height
+    assert_eq!(&png_data[16..24], &[0, 0, 0, 84, 0, 0, 0, 84]);
+
+    assert!(store
+        .get_image_data("MyMissing", 1, coreui::rendition::Idiom::Universal)
+        .expect("Unable to encode image")
+        .is_none());
+}
+
+#[test]
+fn reference_rendition() {
+    let mut reference = data_csi_header(b"");
+    reference.csimetadata.layout = coreui::rendition::LayoutType32::InternalReference;
+    reference.csibitmaplist.rendition_length = 16;
+    reference.rendition_data = None;
+    let mut data = Cursor::new(Vec::<u8>::new());
+    reference
+        .write_le(&mut data)
+        .expect("Unable to write header");
+    // 16 bytes of payload that isn't a Rendition
+    data.write_all(b"INLK\0\0\0\0\0\0\0\0\0\0\0\0")
+        .expect("Unable to write payload");
+    data_csi_header(b"hello")
+        .write_le(&mut data)
+        .expect("Unable to write header");
+
+    data.set_position(0);
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse reference");
+    assert_eq!(
+        csi_header.csimetadata.layout,
+        coreui::rendition::LayoutType32::InternalReference
+    );
+    assert!(csi_header.rendition_data.is_none());
+
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse data");
+    assert_eq!(csi_header.raw_bytes(), Some(&b"hello"[..]));
+}
+
+#[test]
+fn internal_link_frame() {
+    let mut payload = b"KLNI".to_vec();
+    for value in [1u32, 2, 3, 4, 5] {
+        payload.extend(value.to_le_bytes());
+    }
+    payload.extend(0x0Cu16.to_le_bytes());
+    // key of the packed image
+    payload.extend([0, 0, 0, 0, 1, 0, 17, 0, 0x39, 0x30]);
+
+    let mut reference = data_csi_header(b"");
+    reference.csimetadata.layout = coreui::rendition::LayoutType32::InternalReference;
+    reference.csibitmaplist.rendition_length = payload.len() as u32;
+    reference.rendition_data = None;
+    let mut data = Cursor::new(Vec::<u8>::new());
+    reference
+        .write_le(&mut data)
+        .expect("Unable to write header");
+    data.write_all(&payload).expect("Unable to write payload");
+    data_csi_header(b"hello")
+        .write_le(&mut data)
+        .expect("Unable to write header");
+
+    data.set_position(0);
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse reference");
+    assert_eq!(
+        csi_header.internal_link_frame(),
+        Some(coreui::SpriteRect {
+            x: 2,
+            y: 3,
+            width: 4,
+            height: 5,
+        })
+    );
+    let rendition_link = csi_header.rendition_link().expect("No link found");
+    assert_eq!(
+        rendition_link.layout(),
+        coreui::rendition::LayoutType32::Image
+    );
+    assert_eq!(rendition_link.identifier(), Some(12345));
+
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse data");
+    assert_eq!(csi_header.raw_bytes(), Some(&b"hello"[..]));
+    assert_eq!(csi_header.internal_link_frame(), None);
+}
+
+#[test]
+fn layer_reference() {
+    let mut payload = b"KLNI".to_vec();
+    for value in [1u32, 10, 20, 30, 40] {
+        payload.extend(value.to_le_bytes());
+    }
+    payload.extend(0x09u16.to_le_bytes());
+    // key of the layer: hotspot (0, 0), Identifier 7 and Scale 2
+    payload.extend([0, 0, 0, 0, 2, 0, 17, 0, 7, 0, 12, 0, 2, 0]);
+
+    let mut reference = data_csi_header(b"");
+    reference.csimetadata.layout = coreui::rendition::LayoutType32::LayerReference;
+    reference.csibitmaplist.rendition_length = payload.len() as u32;
+    reference.rendition_data = None;
+    let mut data = Cursor::new(Vec::<u8>::new());
+    reference
+        .write_le(&mut data)
+        .expect("Unable to write header");
+    data.write_all(&payload).expect("Unable to write payload");
+
+    data.set_position(0);
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse reference");
+    let rendition_link = csi_header.rendition_link().expect("No link found");
+    assert_eq!(rendition_link.identifier(), Some(7));
+    assert_eq!(
+        rendition_link.frame(),
+        coreui::SpriteRect {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+        }
+    );
+    assert_eq!(
+        rendition_link.layout(),
+        coreui::rendition::LayoutType32::Vector
+    );
+
+    // the key format orders the attributes, Scale before Identifier
+    let mut layer = data_csi_header(b"");
+    layer.csimetadata.layout = coreui::rendition::LayoutType32::Vector;
+    layer.csimetadata.name = common::str_to_sized_slice128("Layer.pdf");
+    let asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Scale,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            (
+                [2, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                layer,
+            ),
+            (
+                [1, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                csi_header.clone(),
+            ),
+        ],
+    );
+    let (layer_key, layer) = asset_storage
+        .resolve_link(rendition_link)
+        .expect("Unable to resolve link");
+    assert_eq!(layer_key.raw[..2], [2, 7]);
+    assert_eq!(layer.csimetadata.name(), "Layer.pdf");
+}
+
+#[test]
+fn attribute_short_name() {
+    let asset_storage = read_car(CAR_PATH);
+    let short_names: Vec<&str> = asset_storage
+        .theme_store
+        .store
+        .renditionkeyfmt
+        .attributes()
+        .iter()
+        .map(|attribute| attribute.short_name())
+        .collect();
+    assert_eq!(
+        short_names,
+        vec![
+            "appearance",
+            "scale",
+            "idiom",
+            "subtype",
+            "deployment_target",
+            "graphics_class",
+            "memory_class",
+            "display_gamut",
+            "direction",
+            "size_class_horizontal",
+            "size_class_vertical",
+            "identifier",
+            "element",
+            "part",
+            "state",
+            "value",
+            "dimension1",
+            "dimension2",
+        ]
+    );
+}
+
+#[test]
+fn car_error_truncated() {
+    let data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let path = std::env::temp_dir().join(format!("carutil-truncated-{}.car", std::process::id()));
+    std::fs::write(&path, &data[..512]).expect("Unable to write truncated file");
+
+    let result = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+    std::fs::remove_file(&path).expect("Unable to clean up");
+    match result {
+        Err(error::CarError::Truncated) => {}
+        Err(err) => panic!("Expected Truncated error, got {:?}", err),
+        Ok(_) => panic!("Parsed a truncated file"),
+    }
+
+    let result = coreui::CarUtilAssetStorage::from("./tests/Missing.car", false);
+    assert!(matches!(result, Err(error::CarError::Io(_))));
+}
+
+#[test]
+fn car_error_unknown_attribute_type() {
+    let mut data = b"tmfk".to_vec();
+    data.extend_from_slice(&1u32.to_le_bytes()); // version
+    data.extend_from_slice(&2u32.to_le_bytes()); // max_count
+    for attribute_type in [7u32, 99] {
+        data.extend_from_slice(&attribute_type.to_le_bytes());
+    }
+
+    let err = coreui::rendition::KeyFormat::read_le(&mut Cursor::new(data))
+        .expect_err("Parsed an unknown attribute type");
+    assert!(matches!(
+        error::CarError::from(err),
+        error::CarError::UnknownAttributeType(99)
+    ));
+}
+
+#[test]
+fn unknown_layout() {
+    let mut data = Cursor::new(Vec::<u8>::new());
+    data_csi_header(b"hello")
+        .write_le(&mut data)
+        .expect("Unable to write header");
+    // layout follows the magic, 7 header fields and the modification time
+    data.get_mut()[36..40].copy_from_slice(&0x1234u32.to_le_bytes());
+    data_csi_header(b"world")
+        .write_le(&mut data)
+        .expect("Unable to write header");
+
+    data.set_position(0);
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse layout");
+    assert_eq!(
+        csi_header.csimetadata.layout,
+        coreui::rendition::LayoutType32::Unknown(0x1234)
+    );
+    assert_eq!(csi_header.raw_bytes(), Some(&b"hello"[..]));
+    assert!(matches!(
+        csi_header.write_data(&mut vec![]),
+        Err(error::CarError::UnsupportedLayout(0x1234))
+    ));
+
+    // the layout is written back as is
+    let mut written = Cursor::new(Vec::<u8>::new());
+    csi_header
+        .write_le(&mut written)
+        .expect("Unable to write header");
+    assert_eq!(&written.get_ref()[36..40], &0x1234u32.to_le_bytes());
+
+    let csi_header = coreui::csi::Header::read_le(&mut data).expect("Unable to parse data");
+    assert_eq!(csi_header.raw_bytes(), Some(&b"world"[..]));
+
+    assert_eq!(
+        coreui::rendition::LayoutType32::from_u32(0x3F1),
+        coreui::rendition::LayoutType32::Color
+    );
+    assert_eq!(coreui::rendition::LayoutType32::Color.to_u32(), 0x3F1);
+}
+
+#[test]
+fn read_all_concatenated() {
+    let mut file = std::fs::File::open(CONCATENATED_CAR_PATH).expect("Unable to open fixture");
+    let catalogs =
+        coreui::CarUtilAssetStorage::read_all(&mut file).expect("Unable to parse catalogs");
+    assert_eq!(catalogs.len(), 2);
+    for catalog in &catalogs {
+        assert_eq!(catalog.theme_store.store.imagedb.len(), 7);
+        assert_eq!(
+            catalog.theme_store.store.header.storage_timestamp,
+            1539543253
+        );
+    }
+    let texts: Vec<&[u8]> = catalogs
+        .iter()
+        .map(|catalog| {
+            rendition_named(&catalog.theme_store.store, "text.txt")
+                .raw_bytes()
+                .expect("No text found")
+        })
+        .collect();
+    assert_eq!(texts, vec![&b"blog.timac.org"[..], &b"blog.timac.net"[..]]);
+}
+
+#[test]
+fn facet_appearances() {
+    let asset_storage = read_car(CAR_PATH);
+    let facet = asset_storage
+        .theme_store
+        .store
+        .facet("MyPNG")
+        .expect("No facet found");
+    assert_eq!(facet.appearances(), vec!["UIAppearanceAny"]);
+
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Appearance,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            (
+                [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[1.0, 1.0, 1.0, 1.0]),
+            ),
+            (
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[0.0, 0.0, 0.0, 1.0]),
+            ),
+            (
+                [0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[1.0, 0.0, 0.0, 1.0]),
+            ),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![
+        ("MyDynamicColor".to_string(), facet_key_token(1)),
+        ("MyLightColor".to_string(), facet_key_token(2)),
+    ];
+    asset_storage.appearancedb = Some(BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ]));
+
+    let appearances = |name| {
+        asset_storage
+            .facet(name)
+            .expect("No facet found")
+            .appearances()
+    };
+    assert_eq!(
+        appearances("MyDynamicColor"),
+        vec!["UIAppearanceAny", "UIAppearanceDark"]
+    );
+    assert_eq!(appearances("MyLightColor"), vec!["UIAppearanceAny"]);
+}
+
+#[test]
+fn decode_jpeg() {
+    let asset_storage = read_car(CAR_PATH);
+    let csi_header = rendition_named(&asset_storage.theme_store.store, "TimacJPG.jpg");
+    assert!(csi_header.is_jpeg());
+
+    // same payload, but only the data says it's a JPEG
+    let mut argb_header = csi_header.clone();
+    argb_header.pixel_format = coreui::csi::PixelFormat::ARGB;
+    assert!(argb_header.is_jpeg());
+
+    for csi_header in [csi_header, &argb_header] {
+        let decoded_image = csi_header.decoded_image().expect("Unable to decode JPEG");
+        assert_eq!((decoded_image.width, decoded_image.height), (200, 200));
+        assert_eq!(decoded_image.pixels.len(), 200 * 200 * 4);
+        assert!(decoded_image
+            .pixels
+            .chunks_exact(4)
+            .all(|pixel| pixel[3] == 0xff));
+        assert_eq!(
+            csi_header.decoded_pixels().expect("Unable to decode JPEG"),
+            decoded_image.pixels
+        );
+    }
+
+    let timac = rendition_named(&asset_storage.theme_store.store, "Timac.png");
+    assert!(!timac.is_jpeg());
+}
+
+#[test]
+fn decode_zip_theme() {
+    let bgra = [1u8, 2, 3, 4].repeat(4);
+    let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(&bgra).expect("Unable to compress");
+    let raw_data = encoder.finish().expect("Unable to compress");
+
+    let mut csi_header = data_csi_header(b"");
+    csi_header.width = 2;
+    csi_header.height = 2;
+    csi_header.pixel_format = coreui::csi::PixelFormat::ARGB;
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+        version: 0,
+        compression_type: coreui::rendition::CompressionType::ZIP,
+        _raw_data_length: raw_data.len() as u32,
+        raw_data: common::RawData(raw_data),
+    });
+
+    assert_eq!(
+        csi_header
+            .decoded_pixels()
+            .expect("Unable to decode pixels"),
+        [3u8, 2, 1, 4].repeat(4)
+    );
+}
+
+#[test]
+fn write_png() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let csi_header = rendition_named(store, "Timac@3x.png");
+    let mut png_data = vec![];
+    csi_header
+        .write_png(&mut png_data)
+        .expect("Unable to write PNG");
+    let mut reader = png::Decoder::new(Cursor::new(png_data))
+        .read_info()
+        .expect("Unable to read PNG");
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).expect("Unable to read PNG");
+    assert_eq!((info.width, info.height), (84, 84));
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+
+    // premultiplied components are scaled back up by the alpha
+    let decoded_pixels = csi_header
+        .decoded_pixels()
+        .expect("Unable to decode pixels");
+    let (decoded_pixel, png_pixel) = decoded_pixels
+        .chunks_exact(4)
+        .zip(pixels.chunks_exact(4))
+        .find(|(pixel, _)| pixel[3] > 0 && pixel[3] < 255 && pixel[0] > 0)
+        .expect("No translucent pixel found");
+    let alpha = decoded_pixel[3] as u32;
+    assert_eq!(png_pixel[3], decoded_pixel[3]);
+    assert_eq!(
+        png_pixel[0] as u32,
+        ((decoded_pixel[0] as u32 * 255 + alpha / 2) / alpha).min(255)
+    );
+
+    for name in ["text.txt", "MyColor"] {
+        assert!(matches!(
+            rendition_named(store, name).write_png(&mut vec![]),
+            Err(error::CarError::NotAnImage)
+        ));
+    }
+}
+
+#[test]
+fn alpha_mask() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let csi_header = rendition_named(store, "Timac@3x.png");
+    let mask = csi_header.alpha_mask().expect("Unable to get alpha mask");
+    assert_eq!((mask.width, mask.height), (84, 84));
+    let alpha: Vec<u8> = csi_header
+        .decoded_pixels()
+        .expect("Unable to decode pixels")
+        .chunks_exact(4)
+        .map(|pixel| pixel[3])
+        .collect();
+    assert_eq!(mask.pixels, alpha);
+    assert!(mask.pixels.iter().any(|&alpha| alpha < 255));
+
+    let mask = rendition_named(store, "TimacJPG.jpg")
+        .alpha_mask()
+        .expect("Unable to get alpha mask");
+    assert_eq!(mask.pixels.len(), 200 * 200);
+    assert!(mask.pixels.iter().all(|&alpha| alpha == 255));
+}
+
+#[test]
+fn supports_dark_mode() {
+    let asset_storage = read_car(CAR_PATH);
+    assert!(!asset_storage.theme_store.store.supports_dark_mode());
+
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Appearance,
+            coreui::rendition::AttributeType::Identifier,
+        ],
+        vec![
+            (
+                [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[1.0, 1.0, 1.0, 1.0]),
+            ),
+            (
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                color_csi_header(&[0.0, 0.0, 0.0, 1.0]),
+            ),
+        ],
+    );
+    asset_storage.appearancedb = Some(BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ]));
+    assert!(asset_storage.supports_dark_mode());
+    assert_eq!(
+        asset_storage.appearance_names(),
+        HashMap::from([
+            (0, "UIAppearanceAny".to_string()),
+            (1, "UIAppearanceDark".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn packed_image_shared_alpha() {
+    // 4x2 packed image with two 2x2 sprites, the color planes are opaque and
+    // the alpha lives in a plane shared by both sprites
+    let packed_image = coreui::csi::DecodedImage {
+        width: 4,
+        height: 2,
+        pixels: [[10u8, 20, 30, 255], [40, 50, 60, 255]]
+            .iter()
+            .flat_map(|pixel| pixel.repeat(2))
+            .collect::<Vec<u8>>()
+            .repeat(2),
+    };
+    let shared_alpha = coreui::csi::GrayImage {
+        width: 4,
+        height: 2,
+        pixels: vec![0, 64, 128, 255, 1, 2, 3, 4],
+    };
+
+    let sprite = packed_image
+        .sub_image(
+            &coreui::SpriteRect {
+                x: 2,
+                y: 0,
+                width: 2,
+                height: 2,
+            },
+            Some(&shared_alpha),
+        )
+        .expect("Unable to extract sprite");
+    assert_eq!((sprite.width, sprite.height), (2, 2));
+    assert_eq!(
+        sprite.pixels,
+        vec![40, 50, 60, 128, 40, 50, 60, 255, 40, 50, 60, 3, 40, 50, 60, 4]
+    );
+
+    let sprite = packed_image
+        .sub_image(
+            &coreui::SpriteRect {
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 1,
+            },
+            None,
+        )
+        .expect("Unable to extract sprite");
+    assert_eq!(sprite.pixels, vec![10, 20, 30, 255, 10, 20, 30, 255]);
+
+    assert!(packed_image
+        .sub_image(
+            &coreui::SpriteRect {
+                x: 3,
+                y: 0,
+                width: 2,
+                height: 2,
+            },
+            None,
+        )
+        .is_err());
+    // x + width wraps around u32
+    assert!(packed_image
+        .sub_image(
+            &coreui::SpriteRect {
+                x: 2,
+                y: 0,
+                width: u32::MAX,
+                height: 1,
+            },
+            None,
+        )
+        .is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn export_xcassets() {
+    let output_dir = std::env::temp_dir().join(format!("carutil-xcassets-{}", std::process::id()));
+    let asset_storage = read_car(CAR_PATH);
+    let warnings = asset_storage
+        .theme_store
+        .store
+        .export_xcassets(&output_dir)
+        .expect("Unable to export");
+    assert!(warnings.is_empty());
+    assert!(output_dir.join("Contents.json").is_file());
+
+    let image_set = output_dir.join("MyPNG.imageset");
+    let contents: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(image_set.join("Contents.json")).expect("No Contents.json"),
+    )
+    .expect("Invalid Contents.json");
+    let mut images: Vec<(String, String, String)> = contents["images"]
+        .as_array()
+        .expect("No images")
+        .iter()
+        .map(|image| {
+            (
+                image["filename"].as_str().unwrap().to_string(),
+                image["idiom"].as_str().unwrap().to_string(),
+                image["scale"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    images.sort();
+    assert_eq!(
+        images,
+        vec![
+            (
+                "Timac.png".to_string(),
+                "universal".to_string(),
+                "1x".to_string()
+            ),
+            (
+                "Timac@2x.png".to_string(),
+                "universal".to_string(),
+                "2x".to_string()
+            ),
+            (
+                "Timac@3x.png".to_string(),
+                "universal".to_string(),
+                "3x".to_string()
+            ),
+        ]
+    );
+    for (filename, _, _) in &images {
+        let png_data = std::fs::read(image_set.join(filename)).expect("Image not exported");
+        assert!(png_data.starts_with(b"\x89PNG"));
+    }
+    assert!(output_dir.join("MyColor.colorset/Contents.json").is_file());
+    assert_eq!(
+        std::fs::read(output_dir.join("MyText.dataset/text.txt"))
+            .expect("Data not exported")
+            .len(),
+        14
+    );
+
+    std::fs::remove_dir_all(&output_dir).expect("Unable to clean up");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn export_xcassets_skipped_facets() {
+    let output_dir =
+        std::env::temp_dir().join(format!("carutil-xcassets-skipped-{}", std::process::id()));
+    let mut image = data_csi_header(b"");
+    image.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+    let mut stack = data_csi_header(b"");
+    stack.csimetadata.layout = coreui::rendition::LayoutType32::LayerStack;
+    stack.rendition_data = None;
+    let mut raw_data = vec![];
+    lzfse_rust::encode_bytes(b"hello", &mut raw_data).expect("Unable to compress");
+    let mut compressed = data_csi_header(b"");
+    compressed.rendition_data = Some(coreui::rendition::Rendition::Theme {
+        version: 0,
+        compression_type: coreui::rendition::CompressionType::LZFSE,
+        _raw_data_length: raw_data.len() as u32,
+        raw_data: common::RawData(raw_data),
+    });
+    let key = |identifier, scale| {
+        let mut raw = [0; 18];
+        raw[0] = identifier;
+        raw[1] = scale;
+        raw
+    };
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![
+            coreui::rendition::AttributeType::Identifier,
+            coreui::rendition::AttributeType::Scale,
+        ],
+        vec![
+            (key(1, 1), data_csi_header(b"data")),
+            (key(1, 2), image),
+            (key(2, 0), stack),
+            (key(3, 0), compressed),
+        ],
+    );
+    asset_storage.facetkeysdb = vec![
+        ("MyMixed".to_string(), facet_key_token(1)),
+        ("MyStack".to_string(), facet_key_token(2)),
+        ("MyData".to_string(), facet_key_token(3)),
+    ];
+
+    let warnings = asset_storage
+        .export_xcassets(&output_dir)
+        .expect("Unable to export");
+    assert_eq!(
+        warnings,
+        vec![
+            coreui::ExportWarning::MixedLayouts {
+                facet: "MyMixed".to_string(),
+                layouts: vec![
+                    coreui::rendition::LayoutType32::Data,
+                    coreui::rendition::LayoutType32::Image,
+                ],
+            },
+            coreui::ExportWarning::UnhandledLayout {
+                facet: "MyStack".to_string(),
+                layout: coreui::rendition::LayoutType32::LayerStack,
+            },
+        ]
+    );
+    assert!(!output_dir.join("MyMixed.dataset").exists());
+    assert!(!output_dir.join("MyMixed.imageset").exists());
+    // data is decompressed like write_data does
+    assert_eq!(
+        std::fs::read(output_dir.join("MyData.dataset/CoreStructuredImage"))
+            .expect("Data not exported"),
+        b"hello"
+    );
+
+    std::fs::remove_dir_all(&output_dir).expect("Unable to clean up");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn export_xcassets_vector() {
+    let output_dir =
+        std::env::temp_dir().join(format!("carutil-xcassets-vector-{}", std::process::id()));
+    let mut csi_header = data_csi_header(b"%PDF-1.3\n%%EOF\n");
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Vector;
+    csi_header.csimetadata.name = common::str_to_sized_slice128("MySymbol.pdf");
+    csi_header.rendition_data = Some(coreui::rendition::Rendition::Vector {
+        version: 1,
+        _raw_data_length: 15,
+        pdf_data: common::RawData(b"%PDF-1.3\n%%EOF\n".to_vec()),
+    });
+    let mut asset_storage = asset_storage_with_renditions(
+        vec![coreui::rendition::AttributeType::Identifier],
+        vec![(
+            [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            csi_header,
+        )],
+    );
+    asset_storage.facetkeysdb = vec![("MySymbol".to_string(), facet_key_token(1))];
+    asset_storage
+        .export_xcassets(&output_dir)
+        .expect("Unable to export");
+
+    let image_set = output_dir.join("MySymbol.imageset");
+    let contents: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(image_set.join("Contents.json")).expect("No Contents.json"),
+    )
+    .expect("Invalid Contents.json");
+    assert_eq!(
+        contents["images"],
+        serde_json::json!([{ "filename": "MySymbol.pdf", "idiom": "universal" }])
+    );
+    assert_eq!(
+        contents["properties"]["preserves-vector-representation"],
+        true
+    );
+    assert_eq!(
+        std::fs::read(image_set.join("MySymbol.pdf")).expect("PDF not exported"),
+        b"%PDF-1.3\n%%EOF\n"
+    );
+
+    std::fs::remove_dir_all(&output_dir).expect("Unable to clean up");
+}
+
+#[test]
+fn from_reader() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let file = std::fs::File::open(CAR_PATH).expect("Unable to open Assets.car");
+    let reader_asset_storage =
+        coreui::CarUtilAssetStorage::from_reader(file).expect("Unable to parse Assets.car");
+    let data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let bytes_asset_storage =
+        coreui::CarUtilAssetStorage::from_bytes(&data).expect("Unable to parse Assets.car");
+
+    let store = &asset_storage.theme_store.store;
+    for other_store in [
+        &reader_asset_storage.theme_store.store,
+        &bytes_asset_storage.theme_store.store,
+    ] {
+        assert_eq!(other_store.imagedb.len(), store.imagedb.len());
+        assert_eq!(
+            other_store.rendition_sha_digests,
+            store.rendition_sha_digests
+        );
+        assert_eq!(other_store.facetkeysdb.len(), store.facetkeysdb.len());
+        assert_eq!(
+            other_store.renditionkeyfmt.attributes(),
+            store.renditionkeyfmt.attributes()
+        );
+    }
+
+    assert!(matches!(
+        coreui::CarUtilAssetStorage::from_bytes(&[]),
+        Err(error::CarError::Truncated)
+    ));
+}
+
+#[test]
+fn key_format_inflated_max_count() {
+    let mut data = b"tmfk".to_vec();
+    data.extend_from_slice(&1u32.to_le_bytes()); // version
+    data.extend_from_slice(&1000u32.to_le_bytes()); // max_count
+    for attribute_type in [7u32, 17] {
+        data.extend_from_slice(&attribute_type.to_le_bytes());
+    }
+
+    let err = coreui::rendition::KeyFormat::read_le(&mut Cursor::new(data.clone()))
+        .expect_err("Parsed an inflated max_count");
+    assert!(matches!(
+        error::CarError::from(err),
+        error::CarError::KeyFormatMismatch {
+            max_count: 1000,
+            available: 2
+        }
+    ));
+
+    // more attributes than a rendition key holds
+    data[8..12].copy_from_slice(&19u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4 * 17]);
+    let err = coreui::rendition::KeyFormat::read_le(&mut Cursor::new(data))
+        .expect_err("Parsed an inflated max_count");
+    assert!(matches!(
+        error::CarError::from(err),
+        error::CarError::KeyFormatMismatch {
+            max_count: 19,
+            available: 18
+        }
+    ));
+}
+
+#[test]
+fn iter_renditions() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(store.len(), 7);
+    assert!(!store.is_empty());
+
+    let image_count = store
+        .iter()
+        .filter(|(_, csi_header)| {
+            csi_header.csimetadata.layout == coreui::rendition::LayoutType32::Image
+        })
+        .count();
+    assert_eq!(image_count, 4);
+    // still usable after iterating
+    assert_eq!(store.iter().count(), store.len());
+
+    assert!(asset_storage_with_renditions(vec![], vec![]).is_empty());
+}
+
+#[test]
+fn decode_into_reused_buffer() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let mut buffer = vec![];
+    for (name, size) in [
+        ("Timac@3x.png", 84),
+        ("Timac.png", 28),
+        ("TimacJPG.jpg", 200),
+    ] {
+        let csi_header = rendition_named(store, name);
+        let bitmap_layout = csi_header
+            .decode_into(&mut buffer)
+            .expect("Unable to decode pixels");
+        assert_eq!(
+            bitmap_layout,
+            coreui::csi::BitmapLayout {
+                width: size,
+                height: size,
+                bytes_per_row: size * 4,
+            }
+        );
+        assert_eq!(
+            buffer,
+            csi_header
+                .decoded_pixels()
+                .expect("Unable to decode pixels")
+        );
+    }
+}
+
+#[test]
+fn compression_histogram() {
+    let asset_storage = read_car(CAR_PATH);
+    let histogram = asset_storage.theme_store.store.compression_histogram();
+    assert_eq!(
+        histogram,
+        HashMap::from([
+            (coreui::rendition::CompressionType::PaletteImg, 3),
+            (coreui::rendition::CompressionType::Uncompressed, 2),
+        ])
+    );
+}
+
+#[test]
+fn asset_by_name() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+
+    let mut names: Vec<String> = store
+        .asset_by_name("MyPNG")
+        .iter()
+        .map(|csi_header| csi_header.csimetadata.name())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["Timac.png", "Timac@2x.png", "Timac@3x.png"]);
+    // served from the index the second time
+    assert_eq!(store.asset_by_name("MyPNG").len(), 3);
+    assert!(store.asset_by_name("MyMissingAsset").is_empty());
+
+    let csi_header = store
+        .rendition_by_name("Timac@2x.png")
+        .expect("No rendition found");
+    assert_eq!(csi_header.width, 56);
+    assert!(store.rendition_by_name("MyPNG").is_none());
+}
+
+#[test]
+fn filter_renditions() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let names = |filter: coreui::RenditionFilter| {
+        let mut names: Vec<String> = store
+            .filter(filter)
+            .iter()
+            .map(|csi_header| csi_header.csimetadata.name())
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(
+        names(coreui::RenditionFilter::default().scale(2)),
+        vec!["Timac@2x.png"]
+    );
+    assert_eq!(
+        names(
+            coreui::RenditionFilter::default()
+                .idiom(coreui::rendition::Idiom::Universal)
+                .scale(3)
+                .appearance("UIAppearanceAny")
+        ),
+        vec!["Timac@3x.png"]
+    );
+    assert!(
+        names(coreui::RenditionFilter::default().idiom(coreui::rendition::Idiom::Pad)).is_empty()
+    );
+    assert_eq!(names(coreui::RenditionFilter::default()).len(), 7);
+}
+
+#[test]
+fn raw_key() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let renditions = store.renditions_for_name("MyPNG");
+    assert_eq!(renditions.len(), 3);
+    for (rendition_key, _) in renditions {
+        let raw_key = rendition_key.raw_key(&store.renditionkeyfmt);
+        assert_eq!(raw_key.len(), store.renditionkeyfmt.attributes().len());
+        assert_eq!(store.renditionkeyfmt.decode(raw_key), *rendition_key);
+    }
+
+    let key_format = coreui::rendition::KeyFormat::new(vec![
+        coreui::rendition::AttributeType::Scale,
+        coreui::rendition::AttributeType::Identifier,
+    ]);
+    assert_eq!(key_format.decode(&[2, 42]).raw_key(&key_format), &[2, 42]);
+}
+
+#[test]
+fn big_endian_catalog() {
+    // Assets.car with the CARHEADER, EXTENDED_METADATA, KEYFORMAT, keys, facet
+    // key tokens and CSI headers byte swapped, payloads are left as they are
+    let little_endian = read_car(CAR_PATH);
+    let big_endian = read_car(BIG_ENDIAN_CAR_PATH);
+    let (little_endian, big_endian) = (
+        &little_endian.theme_store.store,
+        &big_endian.theme_store.store,
+    );
+
+    assert_eq!(big_endian.header.magic, 0x43544152);
+    assert_eq!(
+        format!("{:?}", big_endian.header),
+        format!("{:?}", little_endian.header)
+    );
+    assert_eq!(
+        big_endian.deployment_platform(),
+        little_endian.deployment_platform()
+    );
+    assert_eq!(
+        format!("{:?}", big_endian.renditionkeyfmt),
+        format!("{:?}", little_endian.renditionkeyfmt)
+    );
+    assert_eq!(big_endian.facetkeysdb, little_endian.facetkeysdb);
+    assert_eq!(
+        big_endian.imagedb.keys().collect::<Vec<_>>(),
+        little_endian.imagedb.keys().collect::<Vec<_>>()
+    );
+    for (key, csi_header) in &big_endian.imagedb {
+        let expected = &little_endian.imagedb[key];
+        assert_eq!(csi_header.endian, binrw::Endian::Big);
+        assert_eq!(csi_header.csimetadata.name(), expected.csimetadata.name());
+        assert_eq!(csi_header.csimetadata.layout, expected.csimetadata.layout);
+        assert_eq!(
+            (csi_header.width, csi_header.height, csi_header.scale_factor),
+            (expected.width, expected.height, expected.scale_factor)
+        );
+        assert_eq!(
+            format!("{:?}", csi_header.pixel_format),
+            format!("{:?}", expected.pixel_format)
+        );
+        assert_eq!(
+            format!("{:?}", csi_header.properties()),
+            format!("{:?}", expected.properties())
+        );
+        assert_eq!(csi_header.rendition_data, expected.rendition_data);
+    }
+
+    assert_eq!(
+        rendition_named(big_endian, "Timac@2x.png")
+            .decoded_pixels()
+            .expect("Unable to decode"),
+        rendition_named(little_endian, "Timac@2x.png")
+            .decoded_pixels()
+            .expect("Unable to decode")
+    );
+}
+
+#[test]
+fn newer_schema_version() {
+    let asset_storage = coreui::CarUtilAssetStorage::from_bytes(&car_with_schema_version(3))
+        .expect("Unable to parse schema 3 catalog");
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(store.header.schema_version, 3);
+
+    let names: HashSet<&str> = store
+        .facetkeysdb
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    for name in ["MyPNG", "MyColor", "MyJPG", "MyText"] {
+        assert!(names.contains(name), "{} not found", name);
+    }
+    assert_eq!(store.renditions_for_name("MyPNG").len(), 3);
+}
+
+#[test]
+fn unsupported_schema_version() {
+    // identifier attribute of one of the MyPNG facet key tokens
+    let mut identifier = 17u16.to_le_bytes().to_vec();
+    identifier.extend_from_slice(&32625u16.to_le_bytes());
+    let corrupt_facet_keys = |mut data: Vec<u8>| {
+        let position = data
+            .windows(4)
+            .position(|window| window == identifier.as_slice())
+            .expect("No facet key token found");
+        data[position..position + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        data
+    };
+
+    assert!(matches!(
+        coreui::CarUtilAssetStorage::from_bytes(&corrupt_facet_keys(car_with_schema_version(2))),
+        Err(error::CarError::UnknownAttributeType(0xFFFF))
+    ));
+    assert!(matches!(
+        coreui::CarUtilAssetStorage::from_bytes(&corrupt_facet_keys(car_with_schema_version(3))),
+        Err(error::CarError::UnsupportedSchema(3))
+    ));
+}
+
+#[test]
+fn nine_part_slices() {
+    // 3 columns of 5, 18 and 7 pixels by 3 rows of 4, 10 and 6 pixels
+    let mut tlv = 0x3E9u32.to_le_bytes().to_vec();
+    tlv.extend_from_slice(&(4 + 9 * 16u32).to_le_bytes());
+    tlv.extend_from_slice(&9u32.to_le_bytes());
+    for (y, height) in [(0u32, 4u32), (4, 10), (14, 6)] {
+        for (x, width) in [(0u32, 5u32), (5, 18), (23, 7)] {
+            for value in [x, y, width, height] {
+                tlv.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    let mut csi_header = data_csi_header(b"");
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+    csi_header.csibitmaplist.tlv_length = tlv.len() as u32;
+    csi_header.tlv_data = common::RawData(tlv);
+
+    assert_eq!(csi_header.pixel_width(), Some(30));
+    assert_eq!(csi_header.pixel_height(), Some(20));
+    assert_eq!(
+        csi_header.cap_insets(),
+        Some(coregraphics::EdgeInsets {
+            top: 4.0,
+            left: 5.0,
+            bottom: 6.0,
+            right: 7.0,
+        })
+    );
+    match csi_header.properties().as_slice() {
+        [coreui::tlv::RenditionType::Slices { slices, .. }] => {
+            assert_eq!(slices.len(), 9);
+            assert_eq!(
+                slices[4],
+                coreui::tlv::SliceRect {
+                    x: 5,
+                    y: 4,
+                    width: 18,
+                    height: 10,
+                }
+            );
+        }
+        properties => panic!("Unexpected properties {:?}", properties),
+    }
+
+    // a single slice has no caps
+    let mut tlv = 0x3E9u32.to_le_bytes().to_vec();
+    for value in [20u32, 1, 0, 0, 30, 20] {
+        tlv.extend_from_slice(&value.to_le_bytes());
+    }
+    csi_header.tlv_data = common::RawData(tlv);
+    assert_eq!(csi_header.cap_insets(), None);
+    assert_eq!(csi_header.pixel_width(), Some(30));
+}
+
+#[test]
+fn template_rendering_mode() {
+    // the mode is stored in bits 5 to 7 of the rendition flags
+    let mode = |value: u32| coreui::csi::RenditionFlags(value << 5).template_rendering_mode();
+    assert!(matches!(
+        mode(0),
+        Some(coreui::rendition::TemplateMode::Automatic)
+    ));
+    assert!(matches!(
+        mode(1),
+        Some(coreui::rendition::TemplateMode::Original)
+    ));
+    assert!(matches!(
+        mode(2),
+        Some(coreui::rendition::TemplateMode::Template)
+    ));
+    for value in 3..8 {
+        assert!(mode(value).is_none());
+    }
+
+    // other flags don't leak into the mode
+    assert!(matches!(
+        coreui::csi::RenditionFlags(0x10 | 2 << 5 | 0x200).template_rendering_mode(),
+        Some(coreui::rendition::TemplateMode::Template)
+    ));
+}
+
+#[test]
+fn verify_digest() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let catalog = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    assert_eq!(store.imagedb.len(), 7);
+    for rendition_key in store.imagedb.keys() {
+        assert!(store
+            .verify_digest(&catalog, rendition_key)
+            .expect("Unable to verify digest"));
+    }
+
+    // assetutil's SHA1Digest for MyText
+    let (text_key, text) = store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "text.txt")
+        .expect("No rendition found");
+    assert_eq!(
+        hex::encode_upper(text.sha256_digest().expect("Unable to hash")),
+        "D1A38F18DBBEB13BE04B7D5B55A36F3B6636ECF4007129E375D4A15AA45E9CDD"
+    );
+
+    // the same catalog with the text of MyText changed on disk
+    let tampered = std::fs::read(TAMPERED_CAR_PATH).expect("Unable to read Tampered.car");
+    for rendition_key in store.imagedb.keys() {
+        assert_eq!(
+            store
+                .verify_digest(&tampered, rendition_key)
+                .expect("Unable to verify digest"),
+            rendition_key != text_key
+        );
+    }
+
+    assert!(matches!(
+        store.verify_digest(&catalog[..1024], text_key),
+        Err(error::CarError::Truncated)
+    ));
+}
+
+#[test]
+fn blurred_image() {
+    let blurred = |payload: Vec<u8>| {
+        let mut csi_header = data_csi_header(b"");
+        csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Image;
+        csi_header.rendition_data = Some(coreui::rendition::Rendition::Theme {
+            version: 0,
+            compression_type: coreui::rendition::CompressionType::BlurredImage,
+            _raw_data_length: payload.len() as u32,
+            raw_data: common::RawData(payload),
+        });
+        csi_header.blurred_image()
+    };
+
+    let source = rendition_link(
+        42,
+        0,
+        [0, 0, 20, 10],
+        coreui::rendition::LayoutType32::Image,
+    );
+    let mut payload = Cursor::new(4.5f32.to_le_bytes().to_vec());
+    payload.set_position(4);
+    source.write_le(&mut payload).expect("Unable to write link");
+    let blurred_image = blurred(payload.into_inner()).expect("No blur parameters");
+    assert_eq!(blurred_image.radius, 4.5);
+    assert_eq!(blurred_image.source, Some(source));
+
+    // the source rendition is optional
+    let blurred_image = blurred(2f32.to_le_bytes().to_vec()).expect("No blur parameters");
+    assert_eq!(blurred_image.radius, 2.0);
+    assert_eq!(blurred_image.source, None);
+
+    assert!(blurred(vec![]).is_none());
+    assert!(data_csi_header(b"").blurred_image().is_none());
+}
+
+#[test]
+fn multisize_image_set_entries() {
+    let mut image_set = data_csi_header(b"");
+    image_set.csimetadata.layout = coreui::rendition::LayoutType32::MultisizeImage;
+    image_set.rendition_data = Some(coreui::rendition::Rendition::MultisizeImageSet {
+        version: 1,
+        sizes_count: 1,
+        entries: vec![coreui::rendition::MultisizeImageSetEntry {
+            width: 40,
+            height: 40,
+            index: 3,
+            idiom: coreui::rendition::Idiom::Pad,
+        }],
+    });
+    let entries = image_set
+        .multisize_image_set_entries()
+        .expect("No multisize image set entries");
+    assert_eq!(
+        entries,
+        &[coreui::rendition::MultisizeImageSetEntry {
+            width: 40,
+            height: 40,
+            index: 3,
+            idiom: coreui::rendition::Idiom::Pad,
+        }]
+    );
+
+    assert!(data_csi_header(b"").multisize_image_set_entries().is_none());
+}
+
+#[test]
+fn vector_pdf() {
+    let pdf = b"%PDF-1.3\n%%EOF\n";
+    let mut csi_header = data_csi_header(pdf);
+    csi_header.csimetadata.layout = coreui::rendition::LayoutType32::Vector;
+    csi_header.csimetadata.name = common::str_to_sized_slice128("MySymbol");
+    let mut data = Cursor::new(vec![]);
+    csi_header
+        .write_le(&mut data)
+        .expect("Unable to write rendition");
+
+    // the RawData payload of a vector layout is read as a PDF
+    let csi_header = coreui::csi::Header::read_le(&mut Cursor::new(data.into_inner()))
+        .expect("Unable to parse rendition");
+    assert!(matches!(
+        csi_header.rendition_data,
+        Some(coreui::rendition::Rendition::Vector { .. })
+    ));
+    assert_eq!(csi_header.pdf_data(), Some(&pdf[..]));
+    let mut pdf_data = vec![];
+    csi_header
+        .write_pdf(&mut pdf_data)
+        .expect("Unable to write PDF");
+    assert_eq!(pdf_data, pdf);
+    assert!(matches!(
+        csi_header.write_png(&mut vec![]),
+        Err(error::CarError::NotAnImage)
+    ));
+
+    let output_dir = std::env::temp_dir().join("carutil_vector_pdf");
+    let output_path = csi_header
+        .extract(output_dir.to_str().expect("Invalid path"))
+        .expect("Unable to extract")
+        .expect("Nothing extracted");
+    assert!(output_path.ends_with("MySymbol.pdf"));
+    assert_eq!(
+        std::fs::read(&output_path).expect("Unable to read PDF"),
+        pdf
+    );
+    std::fs::remove_dir_all(&output_dir).expect("Unable to clean up");
+
+    assert!(matches!(
+        data_csi_header(pdf).write_pdf(&mut vec![]),
+        Err(error::CarError::UnsupportedLayout(0x3E8))
+    ));
+}
+
+#[test]
+fn write_data() {
+    let asset_storage = read_car(CAR_PATH);
+    let text = rendition_named(&asset_storage.theme_store.store, "text.txt");
+    let mut data = vec![];
+    text.write_data(&mut data).expect("Unable to write data");
+    assert_eq!(data.len(), 14);
+    assert_eq!(Some(&data[..]), text.raw_bytes());
+
+    assert!(matches!(
+        color_csi_header(&[1.0, 0.0, 0.0, 1.0]).write_data(&mut vec![]),
+        Err(error::CarError::UnsupportedLayout(0x3F1))
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn contents_json() {
+    let asset_storage = read_car(CAR_PATH);
+    let store = &asset_storage.theme_store.store;
+    let appearancedb = store.appearancedb.clone().unwrap_or_default();
+    let mut images: Vec<coreui::contents_json::ImageEntry> = store
+        .imagedb
+        .iter()
+        .filter(|(_, csi_header)| csi_header.csimetadata.name().ends_with(".png"))
+        .map(|(rendition_key, csi_header)| {
+            csi_header.to_contents_entry(rendition_key, &store.renditionkeyfmt, &appearancedb)
+        })
+        .collect();
+    images.sort_by(|a, b| a.filename.cmp(&b.filename));
+    images[0].appearances = vec![coreui::contents_json::Appearance::luminosity("dark")];
+
+    // idiom, scale, appearance and subtype of the rendition key
+    let (_, timac) = store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "Timac.png")
+        .expect("Unable to find Timac.png");
+    let key_format = coreui::rendition::KeyFormat::new(vec![
+        coreui::rendition::AttributeType::Idiom,
+        coreui::rendition::AttributeType::Scale,
+        coreui::rendition::AttributeType::Appearance,
+        coreui::rendition::AttributeType::Subtype,
+    ]);
+    let mut raw = [0; 18];
+    raw[..4].copy_from_slice(&[1, 2, 1, 568]);
+    let entry = timac.to_contents_entry(
+        &coreui::rendition::Key { raw },
+        &key_format,
+        &BTreeMap::from([("UIAppearanceDark".to_string(), 1)]),
+    );
+    assert_eq!(entry.idiom, "iphone");
+    assert_eq!(entry.scale, Some("2x".to_string()));
+    assert_eq!(
+        entry.appearances,
+        vec![coreui::contents_json::Appearance::luminosity("dark")]
+    );
+    assert_eq!(entry.subtype, Some("retina4".to_string()));
+
+    // same keys and key order as Xcode
+    let contents = serde_json::to_string(&coreui::contents_json::ContentsJson::new(images))
+        .expect("Unable to serialize Contents.json");
+    assert_eq!(
+        contents,
+        concat!(
+            r#"{"images":["#,
+            r#"{"appearances":[{"appearance":"luminosity","value":"dark"}],"#,
+            r#""filename":"Timac.png","idiom":"universal","scale":"1x"},"#,
+            r#"{"filename":"Timac@2x.png","idiom":"universal","scale":"2x"},"#,
+            r#"{"filename":"Timac@3x.png","idiom":"universal","scale":"3x"}],"#,
+            r#""info":{"author":"xcode","version":1}}"#
+        )
+    );
+}
+
+#[test]
+fn uuid_string() {
+    let asset_storage = read_car(CAR_PATH);
+    assert_eq!(
+        asset_storage.theme_store.store.uuid_string(),
+        "9EA56D07-3242-4F88-8BC1-C16C25EA65F2"
+    );
+
+    let mut asset_storage = asset_storage_with_renditions(vec![], vec![]);
+    asset_storage.header.uuid = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    assert_eq!(
+        asset_storage.uuid_string(),
+        "00010203-0405-0607-0809-0A0B0C0D0E0F"
+    );
+}